@@ -0,0 +1,18 @@
+fn counter() {
+    var count = 0;
+
+    fn increment() {
+        count = count + 1;
+        return count;
+    }
+
+    return increment;
+}
+
+var first = counter();
+var second = counter();
+
+print first();
+print first();
+print first();
+print second();
@@ -0,0 +1,20 @@
+// A `const` can't be assigned to or declared again; a `var` can be assigned
+// to, but declaring the same name twice in one scope is an error too.
+const limit = 3;
+
+fn countdown() {
+    var remaining = limit;
+    while remaining > 0 {
+        print remaining;
+        remaining = remaining - 1;
+    }
+}
+
+countdown();
+
+{
+    // A block can still shadow a name from outside it.
+    const limit = "none left";
+    print limit;
+}
+print limit;
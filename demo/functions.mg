@@ -0,0 +1,14 @@
+fn fib(n) {
+    if n <= 1 {
+        return n;
+    }
+
+    return fib(n - 1) + fib(n - 2);
+}
+
+var i = 0;
+
+while i <= 10 {
+    print fib(i);
+    i = i + 1;
+}
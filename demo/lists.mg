@@ -0,0 +1,16 @@
+var primes = [2, 3, 5, 7, 11];
+
+print primes;
+print primes[0];
+print primes[-1];
+
+primes[1] = 13;
+print primes;
+
+var grid = [[1, 2], [3, 4]];
+grid[1][0] = "three";
+print grid;
+
+var alias = primes;
+alias[0] = 17;
+print primes[0];
@@ -0,0 +1,41 @@
+for var i = 0; i < 10; i = i + 1 {
+    if i == 2 {
+        continue;
+    }
+
+    if i == 6 {
+        break;
+    }
+
+    print i;
+}
+
+var total = 0;
+
+for var row = 1; row <= 3; row = row + 1 {
+    for var column = 1; column <= 3; column = column + 1 {
+        if column > row {
+            break;
+        }
+
+        total = total + row * column;
+    }
+}
+
+print total;
+
+var n = 0;
+
+while true {
+    n = n + 1;
+
+    {
+        if n < 5 {
+            continue;
+        }
+    }
+
+    break;
+}
+
+print n;
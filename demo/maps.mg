@@ -0,0 +1,25 @@
+var ages = { "ada": 36, "alan": 41, 1: "one", true: "yes" };
+
+print ages;
+print ages["ada"];
+print ages[1];
+print ages[true];
+
+ages["grace"] = 85;
+ages["ada"] = 37;
+print ages;
+
+for name in ages {
+    print name;
+}
+
+for prime in [2, 3, 5] {
+    print prime;
+}
+
+{ "inline": 1 };
+
+{
+    var empty = {};
+    print empty;
+}
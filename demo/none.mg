@@ -0,0 +1,14 @@
+// A variable declared without a value holds `none` until it is assigned.
+var found;
+
+for word in ["kiwi", "mango", "plum"] {
+    if word == "mango" {
+        found = word;
+    }
+}
+
+print found;
+print found == none;
+
+fn nothing() {}
+print nothing();
@@ -0,0 +1,27 @@
+// Integers are exact and grow as large as they need to; a literal with a
+// fractional part is a float.
+print 7 / 2;    // true division always gives a float
+print 7 ~/ 2;   // integer division rounds down
+print -7 ~/ 2;
+print -7 % 3;   // the remainder takes the sign of the divisor
+
+print 1 == 1.0;
+print 4 / 2;
+
+print 2 ** 100;
+print 9223372036854775807 + 1;
+print 30!;
+
+print 0xFF + 0o17 + 0b101;
+print 1_000_000;
+print 6.02e23;
+
+// Operators of the same precedence group left to right, except `**`.
+print 10 - 3 - 2;
+print 2 ** 10 ~/ 3 % 5;
+print 2 ** 3 ** 2;
+
+// Comparisons chain: each operand is worked out once.
+var score = 72;
+print 0 <= score < 100;
+print 1 < 3 < 2;
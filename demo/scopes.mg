@@ -0,0 +1,60 @@
+// Assigning to a variable from an inner block changes the outer variable;
+// declaring one with `var` shadows it until the block ends.
+fn main() {
+    var count = 0;
+    while count < 3 {
+        count = count + 1;
+    }
+    print count;
+
+    var name = "outer";
+    {
+        var name = "inner";
+        print name;
+        {
+            name = "changed";
+            var depth = 2;
+            print name + " " + depth;
+        }
+        print name;
+    }
+    print name;
+
+    var total = 0;
+    for var i = 1; i <= 4; i = i + 1 {
+        var doubled = i * 2;
+        if doubled > 4 {
+            total = total + doubled;
+        }
+    }
+    print total;
+
+    var printers = [0, 0, 0];
+    for item in [0, 1, 2] {
+        fn show() {
+            return item;
+        }
+        printers[item] = show;
+    }
+    for show in printers {
+        print show();
+    }
+
+    fn countdown(n) {
+        if n == 0 {
+            return "liftoff";
+        }
+        return countdown(n - 1);
+    }
+    print countdown(5);
+}
+
+main();
+
+var level = "global";
+{
+    var level = "block";
+    level = level + "!";
+    print level;
+}
+print level;
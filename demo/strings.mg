@@ -0,0 +1,11 @@
+var name = "Mango";
+var scores = { "ada": 3 };
+
+print "Hello, ${name}!";
+print "Two plus two is ${2 + 2}, ada scored ${scores["ada"]}.";
+print "Nested: ${"inner ${name}"}";
+print "Tab:\tQuote:\" Backslash:\\ Dollar:\${name} Heart:\u{2665}";
+print "Line one\nLine two";
+print """
+Raw text keeps \n and ${name} as written,
+and spans lines.""";
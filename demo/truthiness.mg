@@ -0,0 +1,20 @@
+// `false`, `none`, zero and empty strings, lists and maps count as false in
+// conditions; every other value counts as true.
+for value in [false, none, 0, 0.0, "", [], 1, "mango", [0]] {
+    if value {
+        print "${value} is truthy";
+    } else {
+        print "${value} is falsy";
+    }
+}
+
+var countdown = 3;
+while countdown {
+    print countdown;
+    countdown = countdown - 1;
+}
+
+// `and` and `or` give back one of their operands.
+var name = "";
+print name or "anonymous";
+print !name;
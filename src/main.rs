@@ -1,17 +1,61 @@
 mod mango;
 
-use std::io::{self, Write};
+use std::io::{self, IsTerminal, Write};
+use std::thread;
 use std::time::Instant;
 
-use mango::interpreter::Interpreter;
+use mango::diagnostic::Diagnostic;
+use mango::error::{MangoError, RuntimeError};
+use mango::interpreter::{Interpreter, STACK_SIZE};
 use mango::parser::Parser;
+use mango::resolver::Resolver;
 use mango::scanner::Scanner;
+use mango::statement::Statement;
+use mango::vm::Vm;
+
+/// Runs parsed programs, either by walking the syntax tree or by compiling
+/// them to bytecode for the VM. Both give the same output.
+enum Runtime {
+    Interpreter(Interpreter),
+    Vm(Vm),
+}
+
+impl Runtime {
+    fn interpret(&mut self, program: Statement) -> Result<(), RuntimeError> {
+        match self {
+            Runtime::Interpreter(interpreter) => interpreter.interpret(program),
+            Runtime::Vm(vm) => vm.interpret(program),
+        }
+    }
+}
 
 fn main() {
-    let interpreter = Interpreter::new();
-    let args: Vec<String> = std::env::args().collect();
+    // The tree-walker recurses on the native stack for every call.
+    let session = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(start)
+        .expect("failed to start the interpreter thread");
+
+    // The panic has already been reported; exit the way a panicking main would.
+    if session.join().is_err() {
+        std::process::exit(101);
+    }
+}
+
+fn start() {
+    let mut args: Vec<String> = std::env::args().collect();
 
-    if args.len() > 1 {
+    let interpreter = match args.iter().position(|arg| arg == "--vm") {
+        Some(index) => {
+            args.remove(index);
+            Runtime::Vm(Vm::new())
+        }
+        None => Runtime::Interpreter(Interpreter::new()),
+    };
+
+    if args.len() > 2 && args[1] == "--doc" {
+        print_docs(&args[2]);
+    } else if args.len() > 1 {
         let file_path = &args[1];
         run_file(file_path, interpreter);
     } else {
@@ -19,24 +63,123 @@ fn main() {
     }
 }
 
-fn run_file(file_path: &String, mut interpreter: Interpreter) {
+fn run(mut scanner: Scanner, interpreter: &mut Runtime) -> Result<(), Vec<MangoError>> {
+    let tokens = scanner.scan().map_err(|e| vec![e.into()])?;
+
+    let (program, errors) = Parser::new(tokens).parse();
+    if !errors.is_empty() {
+        return Err(errors.into_iter().map(MangoError::from).collect());
+    }
+
+    Resolver::new()
+        .resolve(&program)
+        .map_err(|errors| errors.into_iter().map(MangoError::from).collect::<Vec<_>>())?;
+
+    interpreter
+        .interpret(program)
+        .map_err(|e| vec![e.into()])
+}
+
+fn report(errors: &[MangoError], file_name: &str, source: &str) {
+    // Only color output for a terminal, and let NO_COLOR opt out entirely.
+    let color = io::stderr().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+
+    for error in errors {
+        eprintln!(
+            "{}",
+            Diagnostic::from(error).render(file_name, source, color)
+        );
+    }
+}
+
+/// Runs a script, exiting with a non-zero status if it fails so scripts and
+/// CI can tell: 65 for a problem found before it runs, 70 for a runtime error
+/// and 66 if the file can't be read, after the BSD `sysexits` codes.
+fn run_file(file_path: &String, mut interpreter: Runtime) {
     let start = Instant::now();
 
-    match std::fs::read_to_string(file_path) {
-        Ok(content) => {
-            let tokens = Scanner::new(content).scan();
-            let program = Parser::new(tokens).parse();
-            interpreter.interpret(program);
+    let status = match std::fs::read_to_string(file_path) {
+        Ok(content) => match run(Scanner::new(content.clone()), &mut interpreter) {
+            Ok(()) => 0,
+            Err(errors) => {
+                report(&errors, file_path, &content);
+                if let [MangoError::Runtime(_)] = errors.as_slice() {
+                    70
+                } else {
+                    65
+                }
+            }
+        },
+        Err(e) => {
+            eprintln!("Error reading file {}: {}", file_path, e);
+            66
         }
+    };
+
+    println!("Completed in {} milliseconds.", start.elapsed().as_millis());
+    if status != 0 {
+        std::process::exit(status);
+    }
+}
+
+/// Prints the `///` doc comments of a file's top-level declarations.
+fn print_docs(file_path: &String) {
+    let content = match std::fs::read_to_string(file_path) {
+        Ok(content) => content,
         Err(e) => {
             eprintln!("Error reading file {}: {}", file_path, e);
+            return;
         }
+    };
+
+    let tokens = match Scanner::new(content.clone()).scan() {
+        Ok(tokens) => tokens,
+        Err(error) => return report(&[error.into()], file_path, &content),
+    };
+
+    let (program, errors) = Parser::new(tokens).parse();
+    if !errors.is_empty() {
+        let errors: Vec<MangoError> = errors.into_iter().map(MangoError::from).collect();
+        return report(&errors, file_path, &content);
     }
 
-    println!("Completed in {} milliseconds.", start.elapsed().as_millis());
+    let Statement::Program { statements, .. } = program else {
+        return;
+    };
+
+    for statement in statements {
+        let (signature, doc) = match statement {
+            Statement::Function {
+                name,
+                parameters,
+                doc: Some(doc),
+                ..
+            } => (format!("fn {}({})", name, parameters.join(", ")), doc),
+            Statement::VariableDeclaration {
+                name,
+                constant,
+                doc: Some(doc),
+                ..
+            } => {
+                let keyword = if constant { "const" } else { "var" };
+                (format!("{keyword} {name}"), doc)
+            }
+            _ => continue,
+        };
+
+        println!("{signature}");
+        for line in doc.lines() {
+            println!("    {line}");
+        }
+        println!();
+    }
 }
 
-fn repl(mut interpreter: Interpreter) {
+fn repl(mut interpreter: Runtime) {
+    // Everything typed so far. Errors can point into an earlier entry, such
+    // as the body of a function defined there, so all of it is kept.
+    let mut history = String::new();
+
     loop {
         print!("> "); // Print the prompt
         io::stdout().flush().unwrap(); // Ensure the prompt is displayed immediately
@@ -55,10 +198,19 @@ fn repl(mut interpreter: Interpreter) {
 
         let start = Instant::now();
 
-        let tokens = Scanner::new(input.to_string()).scan();
-        let program = Parser::new(tokens).parse();
+        let offset = history.len();
+        let line = history.matches('\n').count() + 1;
+        history.push_str(&input);
+        if !input.ends_with('\n') {
+            history.push('\n');
+        }
 
-        interpreter.interpret(program);
+        // Errors are reported and the session carries on with the same
+        // interpreter, so earlier definitions stay available.
+        let scanner = Scanner::resume(history.clone(), offset, line);
+        if let Err(errors) = run(scanner, &mut interpreter) {
+            report(&errors, "<repl>", &history);
+        }
 
         println!("Completed in {} milliseconds.", start.elapsed().as_millis());
     }
@@ -0,0 +1,792 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
+use super::environment::{Globals, Slot};
+use super::expression::{self, Expression};
+use super::integer::Integer;
+use super::literal::Literal;
+use super::span::Span;
+use super::statement::{self, Statement};
+use super::token::Token;
+use super::token_type::TokenType;
+
+/// A single VM instruction. Operands index into the chunk's constants or
+/// prototypes, into the current frame's slots or upvalues, or into the
+/// globals, or are absolute jump targets within the chunk.
+#[derive(Clone, Copy, Debug)]
+pub enum Instruction {
+    Constant(usize),
+    None,
+    Pop,
+    /// Copies the top of the stack below the value under it, so `a b` becomes
+    /// `b a b`.
+    Tuck,
+
+    GetLocal(usize),
+    SetLocal(usize),
+    GetUpvalue(usize),
+    SetUpvalue(usize),
+    GetGlobal(usize),
+    SetGlobal(usize),
+    DefineGlobal(usize),
+    DefineConstant(usize),
+    /// Moves the local on top of the stack into the closures that captured
+    /// it, then pops it.
+    CloseUpvalue,
+
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    FloorDivide,
+    Remainder,
+    Power,
+    Negate,
+    Not,
+    Factorial,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+
+    Jump(usize),
+    /// Jumps if the value on top of the stack is falsy, leaving it in place.
+    JumpIfFalse(usize),
+    /// Jumps if the value on top of the stack is truthy, leaving it in place.
+    JumpIfTrue(usize),
+
+    Closure(usize),
+    Call(usize),
+    Return,
+
+    List(usize),
+    Map,
+    /// Adds the key and value on top of the stack to the map beneath them.
+    InsertEntry,
+    Index,
+    SetIndex,
+    Stringify,
+    Concat(usize),
+
+    /// Replaces the iterable on top of the stack with a list snapshot of what
+    /// a `for in` loop visits.
+    Iterate,
+    /// Pushes the next item of the snapshot in the given slot, whose counter
+    /// is in the slot after it, or jumps to the target when it is exhausted.
+    Next(usize, usize),
+
+    Print,
+}
+
+/// Compiled code, along with everything its instructions refer to. Spans run
+/// parallel to the code, so a runtime error can point at its source.
+#[derive(Debug, Default)]
+pub struct Chunk {
+    pub code: Vec<Instruction>,
+    pub spans: Vec<Span>,
+    pub constants: Vec<Literal>,
+    pub prototypes: Vec<Rc<Prototype>>,
+}
+
+/// Where a closure finds a captured variable when it is created: in a slot of
+/// the enclosing function, or in one of the enclosing function's own
+/// upvalues.
+#[derive(Clone, Copy, Debug)]
+pub struct UpvalueSource {
+    pub is_local: bool,
+    pub index: usize,
+}
+
+/// A compiled function, before it has captured anything. The top level of a
+/// program is compiled into one too.
+#[derive(Debug)]
+pub struct Prototype {
+    pub name: String,
+    pub arity: usize,
+    pub chunk: Chunk,
+    pub upvalues: Vec<UpvalueSource>,
+}
+
+/// A stack slot of the function being compiled. Slot 0 and the counters of a
+/// `for in` loop are `hidden`: the resolver doesn't number them among the
+/// function's variables.
+struct Local {
+    depth: usize,
+    captured: bool,
+    hidden: bool,
+}
+
+/// The innermost loop being compiled. `locals` is how many locals live across
+/// iterations; `break` and `continue` discard any above that.
+struct Loop {
+    locals: usize,
+    breaks: Vec<usize>,
+    continues: Vec<usize>,
+}
+
+/// The function currently being compiled. Slot 0 holds the function itself.
+struct FunctionState {
+    name: String,
+    arity: usize,
+    chunk: Chunk,
+    locals: Vec<Local>,
+    upvalues: Vec<UpvalueSource>,
+    depth: usize,
+    loops: Vec<Loop>,
+}
+
+impl FunctionState {
+    fn new(name: String, arity: usize, depth: usize) -> Self {
+        Self {
+            name,
+            arity,
+            chunk: Chunk::default(),
+            locals: vec![Local {
+                depth,
+                captured: false,
+                hidden: true,
+            }],
+            upvalues: Vec::new(),
+            depth,
+            loops: Vec::new(),
+        }
+    }
+
+    /// The stack slot of the variable the resolver numbered `index`.
+    fn slot(&self, index: usize) -> usize {
+        self.locals
+            .iter()
+            .enumerate()
+            .filter(|(_, local)| !local.hidden)
+            .nth(index)
+            .map(|(slot, _)| slot)
+            .expect("the resolver only numbers variables in scope")
+    }
+}
+
+/// Lowers a program to bytecode for the `Vm`. Variables go where the
+/// `Resolver` found them: a local of the function itself is a stack slot, one
+/// of an enclosing function an upvalue, and everything else a global, in the
+/// slot `globals` gives its name.
+pub struct Compiler<'a> {
+    functions: Vec<FunctionState>,
+    globals: &'a mut Globals,
+}
+
+impl<'a> Compiler<'a> {
+    pub fn new(globals: &'a mut Globals) -> Self {
+        Self {
+            functions: vec![FunctionState::new("<script>".to_string(), 0, 0)],
+            globals,
+        }
+    }
+
+    /// Compiles a program that has been parsed and resolved without errors,
+    /// which is all it takes for it to compile.
+    pub fn compile(mut self, program: &Statement) -> Rc<Prototype> {
+        program.accept(&mut self);
+        self.emit(Instruction::None, Span::default());
+        self.emit(Instruction::Return, Span::default());
+
+        let function = self.functions.pop().expect("the script is always compiling");
+
+        Rc::new(Prototype {
+            name: function.name,
+            arity: function.arity,
+            chunk: function.chunk,
+            upvalues: function.upvalues,
+        })
+    }
+
+    fn current(&mut self) -> &mut FunctionState {
+        self.functions.last_mut().expect("the script is always compiling")
+    }
+
+    fn emit(&mut self, instruction: Instruction, span: Span) -> usize {
+        let chunk = &mut self.current().chunk;
+        chunk.code.push(instruction);
+        chunk.spans.push(span);
+
+        chunk.code.len() - 1
+    }
+
+    /// Points the jump at `at` to the next instruction to be emitted.
+    fn patch(&mut self, at: usize) {
+        let target = self.current().chunk.code.len();
+
+        match &mut self.current().chunk.code[at] {
+            Instruction::Jump(to) | Instruction::JumpIfFalse(to) | Instruction::JumpIfTrue(to) => {
+                *to = target
+            }
+            Instruction::Next(_, to) => *to = target,
+            instruction => unreachable!("{:?} is not a jump", instruction),
+        }
+    }
+
+    fn constant(&mut self, value: Literal) -> usize {
+        let constants = &mut self.current().chunk.constants;
+        constants.push(value);
+
+        constants.len() - 1
+    }
+
+    fn begin_scope(&mut self) {
+        self.current().depth += 1;
+    }
+
+    fn end_scope(&mut self) {
+        let function = self.current();
+        function.depth -= 1;
+
+        let depth = function.depth;
+        let count = function
+            .locals
+            .iter()
+            .take_while(|local| local.depth <= depth)
+            .count();
+
+        self.discard_above(count);
+        self.current().locals.truncate(count);
+    }
+
+    /// Discards the locals above `count`, innermost first, closing over any
+    /// that were captured. They stay declared, for jumps that leave their
+    /// scope early.
+    fn discard_above(&mut self, count: usize) {
+        let captured: Vec<bool> = self.current().locals[count..]
+            .iter()
+            .rev()
+            .map(|local| local.captured)
+            .collect();
+
+        for captured in captured {
+            let instruction = if captured {
+                Instruction::CloseUpvalue
+            } else {
+                Instruction::Pop
+            };
+            self.emit(instruction, Span::default());
+        }
+    }
+
+    fn add_local(&mut self, hidden: bool) {
+        let function = self.current();
+        let depth = function.depth;
+        function.locals.push(Local {
+            depth,
+            captured: false,
+            hidden,
+        });
+    }
+
+    /// Stores the value on top of the stack in a new variable called `name`.
+    /// Only global constants need marking: the resolver has already checked
+    /// that no local constant is assigned to, and that no local is declared
+    /// twice in one scope.
+    fn declare(&mut self, name: &str, constant: bool, span: Span) {
+        if self.current().depth == 0 {
+            let slot = self.globals.slot(name);
+            if constant {
+                self.emit(Instruction::DefineConstant(slot), span);
+            } else {
+                self.emit(Instruction::DefineGlobal(slot), span);
+            }
+        } else {
+            // The value is already in place: the new slot is the top of the
+            // stack.
+            self.add_local(false);
+        }
+    }
+
+    /// The upvalue of the function at `level` that reaches the variable
+    /// `slot`, adding it, and any the enclosing functions need to pass it
+    /// down, if it is new.
+    fn resolve_upvalue(&mut self, level: usize, slot: Slot) -> usize {
+        let source = if slot.depth == 1 {
+            let enclosing = &mut self.functions[level - 1];
+            let index = enclosing.slot(slot.index);
+            enclosing.locals[index].captured = true;
+            UpvalueSource {
+                is_local: true,
+                index,
+            }
+        } else {
+            let slot = Slot {
+                depth: slot.depth - 1,
+                ..slot
+            };
+            UpvalueSource {
+                is_local: false,
+                index: self.resolve_upvalue(level - 1, slot),
+            }
+        };
+
+        let upvalues = &mut self.functions[level].upvalues;
+        let existing = upvalues
+            .iter()
+            .position(|upvalue| upvalue.is_local == source.is_local && upvalue.index == source.index);
+
+        existing.unwrap_or_else(|| {
+            upvalues.push(source);
+            upvalues.len() - 1
+        })
+    }
+
+    /// Picks the get or set instruction for `name`: a local slot, an upvalue
+    /// or, if the resolver gave it no slot, a global.
+    fn variable(&mut self, name: &str, slot: Option<Slot>, set: bool) -> Instruction {
+        match slot {
+            Some(Slot { depth: 0, index }) => {
+                let slot = self.current().slot(index);
+                if set {
+                    Instruction::SetLocal(slot)
+                } else {
+                    Instruction::GetLocal(slot)
+                }
+            }
+            Some(slot) => {
+                let level = self.functions.len() - 1;
+                let index = self.resolve_upvalue(level, slot);
+                if set {
+                    Instruction::SetUpvalue(index)
+                } else {
+                    Instruction::GetUpvalue(index)
+                }
+            }
+            None => {
+                let global = self.globals.slot(name);
+                if set {
+                    Instruction::SetGlobal(global)
+                } else {
+                    Instruction::GetGlobal(global)
+                }
+            }
+        }
+    }
+
+    fn begin_loop(&mut self) {
+        let locals = self.current().locals.len();
+        self.current().loops.push(Loop {
+            locals,
+            breaks: Vec::new(),
+            continues: Vec::new(),
+        });
+    }
+
+    /// Points the `continue`s of the innermost loop at the next instruction.
+    fn patch_continues(&mut self) {
+        let continues = std::mem::take(
+            &mut self.current().loops.last_mut().expect("inside a loop").continues,
+        );
+        for at in continues {
+            self.patch(at);
+        }
+    }
+
+    /// Points the `break`s of the innermost loop at the next instruction.
+    fn end_loop(&mut self) {
+        let finished = self.current().loops.pop().expect("inside a loop");
+        for at in finished.breaks {
+            self.patch(at);
+        }
+    }
+}
+
+/// The instruction for an arithmetic or comparison `operator`. The parser
+/// builds binary expressions from no other tokens.
+fn binary(operator: &Token) -> Instruction {
+    match operator.kind {
+        TokenType::StarStar => Instruction::Power,
+        TokenType::Star => Instruction::Multiply,
+        TokenType::Slash => Instruction::Divide,
+        TokenType::TildeSlash => Instruction::FloorDivide,
+        TokenType::Percent => Instruction::Remainder,
+        TokenType::Plus => Instruction::Add,
+        TokenType::Minus => Instruction::Subtract,
+
+        TokenType::Less => Instruction::Less,
+        TokenType::LessEqual => Instruction::LessEqual,
+        TokenType::Greater => Instruction::Greater,
+        TokenType::GreaterEqual => Instruction::GreaterEqual,
+        TokenType::EqualEqual => Instruction::Equal,
+        TokenType::BangEqual => Instruction::NotEqual,
+
+        kind => unreachable!("{:?} is not a binary operator", kind),
+    }
+}
+
+impl expression::Visitor<()> for Compiler<'_> {
+    fn visit_binary(&mut self, left: &Expression, operator: &Token, right: &Expression) {
+        left.accept(self);
+        right.accept(self);
+
+        // Errors from the operation point at all of it.
+        let instruction = binary(operator);
+        self.emit(instruction, left.span().to(right.span()));
+    }
+
+    fn visit_comparison(&mut self, operands: &[Expression], operators: &[Token]) {
+        operands[0].accept(self);
+
+        // Every comparison but the last keeps a copy of its right operand
+        // underneath, to be the left operand of the next one.
+        let mut failures = Vec::new();
+        let last = operators.len() - 1;
+        for (i, operator) in operators.iter().enumerate() {
+            operands[i + 1].accept(self);
+            let span = operands[i].span().to(operands[i + 1].span());
+
+            if i == last {
+                self.emit(binary(operator), span);
+            } else {
+                self.emit(Instruction::Tuck, operator.span);
+                self.emit(binary(operator), span);
+                failures.push(self.emit(Instruction::JumpIfFalse(0), operator.span));
+                self.emit(Instruction::Pop, operator.span);
+            }
+        }
+
+        let end = self.emit(Instruction::Jump(0), Span::default());
+
+        // A failed comparison leaves the copy and its `false` behind.
+        for failure in failures {
+            self.patch(failure);
+        }
+        self.emit(Instruction::Pop, Span::default());
+        self.emit(Instruction::Pop, Span::default());
+        let value = self.constant(Literal::Boolean(false));
+        self.emit(Instruction::Constant(value), Span::default());
+
+        self.patch(end);
+    }
+
+    fn visit_logical(&mut self, left: &Expression, operator: &Token, right: &Expression) {
+        left.accept(self);
+
+        // Short-circuit by jumping over the right operand, leaving the left
+        // one as the result.
+        let jump = match operator.kind {
+            TokenType::Or => Instruction::JumpIfTrue(0),
+            TokenType::And => Instruction::JumpIfFalse(0),
+
+            kind => unreachable!("{:?} is not a logical operator", kind),
+        };
+        let end = self.emit(jump, operator.span);
+
+        self.emit(Instruction::Pop, operator.span);
+        right.accept(self);
+        self.patch(end);
+    }
+
+    fn visit_unary(&mut self, operator: &Token, right: &Expression, is_prefix: bool) {
+        let span = if is_prefix {
+            operator.span.to(right.span())
+        } else {
+            right.span().to(operator.span)
+        };
+        right.accept(self);
+
+        let instruction = match (operator.kind, is_prefix) {
+            (TokenType::Bang, true) => Instruction::Not,
+            (TokenType::Minus, true) => Instruction::Negate,
+
+            (TokenType::Bang, false) => Instruction::Factorial,
+
+            (kind, true) => unreachable!("{:?} is not a prefix operator", kind),
+            (kind, false) => unreachable!("{:?} is not a postfix operator", kind),
+        };
+        self.emit(instruction, span);
+    }
+
+    fn visit_literal(&mut self, literal: &Literal) {
+        let constant = self.constant(literal.clone());
+        self.emit(Instruction::Constant(constant), Span::default());
+    }
+
+    fn visit_variable(
+        &mut self,
+        name: &str,
+        slot: &Cell<Option<Slot>>,
+        _global: &Cell<Option<usize>>,
+        span: Span,
+    ) {
+        let instruction = self.variable(name, slot.get(), false);
+        self.emit(instruction, span);
+    }
+
+    fn visit_assignment(
+        &mut self,
+        name: &str,
+        slot: &Cell<Option<Slot>>,
+        _global: &Cell<Option<usize>>,
+        value: &Expression,
+        span: Span,
+    ) {
+        value.accept(self);
+
+        let instruction = self.variable(name, slot.get(), true);
+        self.emit(instruction, span);
+    }
+
+    fn visit_grouping(&mut self, expression: &Expression) {
+        expression.accept(self)
+    }
+
+    fn visit_call(&mut self, callee: &Expression, arguments: &[Expression], span: Span) {
+        callee.accept(self);
+        for argument in arguments {
+            argument.accept(self);
+        }
+        self.emit(Instruction::Call(arguments.len()), span);
+    }
+
+    fn visit_list(&mut self, elements: &[Expression]) {
+        for element in elements {
+            element.accept(self);
+        }
+        self.emit(Instruction::List(elements.len()), Span::default());
+    }
+
+    fn visit_interpolation(&mut self, parts: &[Expression]) {
+        // Each part is turned into text as soon as it is evaluated, so a later
+        // part can't change how an earlier one prints.
+        for part in parts {
+            part.accept(self);
+            self.emit(Instruction::Stringify, part.span());
+        }
+        self.emit(Instruction::Concat(parts.len()), Span::default());
+    }
+
+    fn visit_map(&mut self, entries: &[(Expression, Expression)], span: Span) {
+        self.emit(Instruction::Map, span);
+        for (key, value) in entries {
+            key.accept(self);
+            value.accept(self);
+            self.emit(Instruction::InsertEntry, span);
+        }
+    }
+
+    fn visit_index(&mut self, target: &Expression, index: &Expression, span: Span) {
+        target.accept(self);
+        index.accept(self);
+        self.emit(Instruction::Index, span);
+    }
+
+    fn visit_index_assignment(
+        &mut self,
+        target: &Expression,
+        index: &Expression,
+        value: &Expression,
+        span: Span,
+    ) {
+        target.accept(self);
+        index.accept(self);
+        value.accept(self);
+        self.emit(Instruction::SetIndex, span);
+    }
+}
+
+impl statement::Visitor<()> for Compiler<'_> {
+    fn visit_program(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            statement.accept(self);
+        }
+    }
+
+    fn visit_block(&mut self, statements: &[Statement]) {
+        self.begin_scope();
+        for statement in statements {
+            statement.accept(self);
+        }
+        self.end_scope();
+    }
+
+    fn visit_expression(&mut self, expression: &Expression) {
+        expression.accept(self);
+        self.emit(Instruction::Pop, expression.span());
+    }
+
+    fn visit_variable_declaration(
+        &mut self,
+        name: &str,
+        value: &Expression,
+        constant: bool,
+        span: Span,
+    ) {
+        value.accept(self);
+        self.declare(name, constant, span);
+    }
+
+    fn visit_while(&mut self, condition: &Expression, block: &Statement) {
+        self.visit_for(None, Some(condition), None, block)
+    }
+
+    fn visit_for(
+        &mut self,
+        initializer: Option<&Statement>,
+        condition: Option<&Expression>,
+        increment: Option<&Expression>,
+        body: &Statement,
+    ) {
+        // The loop variable lives in its own scope around the whole loop.
+        self.begin_scope();
+        if let Some(initializer) = initializer {
+            initializer.accept(self);
+        }
+
+        let start = self.current().chunk.code.len();
+        let exit = match condition {
+            Some(condition) => {
+                condition.accept(self);
+                let exit = self.emit(Instruction::JumpIfFalse(0), condition.span());
+                self.emit(Instruction::Pop, condition.span());
+                Some(exit)
+            }
+            None => None,
+        };
+
+        self.begin_loop();
+        body.accept(self);
+
+        self.patch_continues();
+        if let Some(increment) = increment {
+            increment.accept(self);
+            self.emit(Instruction::Pop, increment.span());
+        }
+        self.emit(Instruction::Jump(start), Span::default());
+
+        // The condition is still on the stack when it fails; a `break` has
+        // already left it behind.
+        if let Some(exit) = exit {
+            self.patch(exit);
+            self.emit(Instruction::Pop, Span::default());
+        }
+        self.end_loop();
+        self.end_scope();
+    }
+
+    fn visit_for_in(&mut self, _name: &str, iterable: &Expression, body: &Statement) {
+        // The snapshot and a counter into it are kept in two hidden locals.
+        self.begin_scope();
+        iterable.accept(self);
+        self.emit(Instruction::Iterate, iterable.span());
+        self.add_local(true);
+        let slot = self.current().locals.len() - 1;
+
+        let zero = self.constant(Literal::Integer(Integer::Small(0)));
+        self.emit(Instruction::Constant(zero), Span::default());
+        self.add_local(true);
+
+        // Each item gets a fresh scope, so closures made in the body capture
+        // that iteration's item.
+        self.begin_loop();
+        let start = self.emit(Instruction::Next(slot, 0), iterable.span());
+        self.begin_scope();
+        self.add_local(false);
+        body.accept(self);
+        self.end_scope();
+
+        self.patch_continues();
+        self.emit(Instruction::Jump(start), Span::default());
+        self.patch(start);
+        self.end_loop();
+        self.end_scope();
+    }
+
+    fn visit_break(&mut self) {
+        let locals = self.current().loops.last().expect("inside a loop").locals;
+        self.discard_above(locals);
+
+        let jump = self.emit(Instruction::Jump(0), Span::default());
+        self.current().loops.last_mut().expect("inside a loop").breaks.push(jump);
+    }
+
+    fn visit_continue(&mut self) {
+        let locals = self.current().loops.last().expect("inside a loop").locals;
+        self.discard_above(locals);
+
+        let jump = self.emit(Instruction::Jump(0), Span::default());
+        self.current().loops.last_mut().expect("inside a loop").continues.push(jump);
+    }
+
+    fn visit_if(
+        &mut self,
+        condition: &Expression,
+        then_branch: &Statement,
+        else_branch: Option<&Statement>,
+    ) {
+        condition.accept(self);
+        let otherwise = self.emit(Instruction::JumpIfFalse(0), condition.span());
+        self.emit(Instruction::Pop, condition.span());
+        then_branch.accept(self);
+        let end = self.emit(Instruction::Jump(0), Span::default());
+
+        self.patch(otherwise);
+        self.emit(Instruction::Pop, condition.span());
+        if let Some(else_branch) = else_branch {
+            else_branch.accept(self);
+        }
+        self.patch(end);
+    }
+
+    fn visit_function(
+        &mut self,
+        name: &str,
+        parameters: &[String],
+        body: &Rc<Vec<Statement>>,
+        span: Span,
+    ) {
+        // Declare a local function before compiling its body, so the body can
+        // call it recursively.
+        if self.current().depth > 0 {
+            self.add_local(false);
+        }
+
+        // The body runs in the same scope as the parameters.
+        self.functions
+            .push(FunctionState::new(name.to_string(), parameters.len(), 1));
+        for _ in parameters {
+            self.add_local(false);
+        }
+        for statement in body.iter() {
+            statement.accept(self);
+        }
+        self.emit(Instruction::None, Span::default());
+        self.emit(Instruction::Return, Span::default());
+
+        let function = self.functions.pop().expect("just pushed");
+        let prototype = Rc::new(Prototype {
+            name: function.name,
+            arity: function.arity,
+            chunk: function.chunk,
+            upvalues: function.upvalues,
+        });
+
+        let prototypes = &mut self.current().chunk.prototypes;
+        prototypes.push(prototype);
+        let index = prototypes.len() - 1;
+        self.emit(Instruction::Closure(index), span);
+
+        if self.current().depth == 0 {
+            let slot = self.globals.slot(name);
+            self.emit(Instruction::DefineGlobal(slot), span);
+        }
+    }
+
+    fn visit_return(&mut self, value: Option<&Expression>, span: Span) {
+        match value {
+            Some(value) => value.accept(self),
+            None => {
+                self.emit(Instruction::None, span);
+            }
+        }
+        self.emit(Instruction::Return, span);
+    }
+
+    fn visit_print(&mut self, expression: &Expression) {
+        expression.accept(self);
+        self.emit(Instruction::Print, expression.span());
+    }
+}
@@ -0,0 +1,211 @@
+use std::fmt::Write;
+
+use super::error::MangoError;
+use super::span::Span;
+
+const RED: &str = "\x1b[1;31m";
+const BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+/// A reportable problem with the source, rendered in the style of compiler
+/// output: a headline, the location, the offending line with its span
+/// underlined and an optional help note.
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub title: String,
+    pub message: String,
+    pub span: Span,
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn new(title: String, message: String, span: Span) -> Self {
+        Self {
+            title,
+            message,
+            span,
+            help: None,
+        }
+    }
+
+    pub fn with_help(mut self, help: Option<String>) -> Self {
+        self.help = help;
+        self
+    }
+
+    /// Renders the diagnostic against the source it was produced from. With
+    /// `color` off the output is plain text, suitable for logs.
+    pub fn render(&self, file_name: &str, source: &str, color: bool) -> String {
+        let paint = |style: &str, text: &str| {
+            if color {
+                format!("{style}{text}{RESET}")
+            } else {
+                text.to_string()
+            }
+        };
+
+        // Locate the span from its byte offsets so the snippet is always taken
+        // from the text being rendered. Offsets are kept inside it, on
+        // character boundaries, in case the span came from other text.
+        let start = char_boundary(source, self.span.start);
+        let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[start..].find('\n').map_or(source.len(), |i| start + i);
+        let end = char_boundary(source, self.span.end.clamp(start, line_end));
+
+        let line_number = source[..line_start].matches('\n').count() + 1;
+        let column = source[line_start..start].chars().count() + 1;
+        let line = source[line_start..line_end].trim_end_matches('\r');
+
+        // Keep tabs in the indentation so the carets line up with the snippet.
+        let indent: String = source[line_start..start]
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let underline = "^".repeat(source[start..end].chars().count().max(1));
+        let gutter = " ".repeat(line_number.to_string().len());
+
+        let mut output = String::new();
+        let _ = writeln!(
+            output,
+            "{}{}",
+            paint(RED, &self.title),
+            paint(BOLD, &format!(": {}", self.message))
+        );
+        let _ = writeln!(
+            output,
+            "{gutter}{} {file_name}:{line_number}:{column}",
+            paint(BLUE, "-->")
+        );
+        let _ = writeln!(output, "{gutter} {}", paint(BLUE, "|"));
+        let _ = writeln!(
+            output,
+            "{} {line}",
+            paint(BLUE, &format!("{line_number} |"))
+        );
+        let _ = writeln!(
+            output,
+            "{gutter} {} {indent}{}",
+            paint(BLUE, "|"),
+            paint(RED, &underline)
+        );
+
+        if let Some(help) = &self.help {
+            let _ = writeln!(output, "{gutter} {}", paint(BLUE, "|"));
+            let _ = writeln!(
+                output,
+                "{gutter} {} {}: {help}",
+                paint(BLUE, "="),
+                paint(BOLD, "help")
+            );
+        }
+
+        output
+    }
+}
+
+/// The nearest character boundary at or before `offset`, within `source`.
+fn char_boundary(source: &str, offset: usize) -> usize {
+    let mut offset = offset.min(source.len());
+    while !source.is_char_boundary(offset) {
+        offset -= 1;
+    }
+
+    offset
+}
+
+impl From<&MangoError> for Diagnostic {
+    fn from(error: &MangoError) -> Self {
+        match error {
+            MangoError::Lex(error) => Diagnostic::new(
+                "lex error".to_string(),
+                error.message.clone(),
+                error.span,
+            ),
+            MangoError::Parse(error) => Diagnostic::new(
+                "parse error".to_string(),
+                error.message.clone(),
+                error.span,
+            )
+            .with_help(error.help.clone()),
+            MangoError::Resolve(error) => Diagnostic::new(
+                "resolve error".to_string(),
+                error.message.clone(),
+                error.span,
+            )
+            .with_help(error.help.clone()),
+            MangoError::Runtime(error) => Diagnostic::new(
+                "runtime error".to_string(),
+                error.message.clone(),
+                error.span,
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diagnostic(start: usize, end: usize) -> Diagnostic {
+        Diagnostic::new(
+            "runtime error".to_string(),
+            "Division by zero".to_string(),
+            Span::new(start, end, 1, 1),
+        )
+    }
+
+    #[test]
+    fn underlines_the_span_on_its_line() {
+        let source = "var a = 1;\nprint a / 0;\n";
+        let output = diagnostic(17, 22).render("main.mg", source, false);
+
+        assert_eq!(
+            output,
+            "runtime error: Division by zero
+ --> main.mg:2:7
+  |
+2 | print a / 0;
+  |       ^^^^^
+"
+        );
+    }
+
+    #[test]
+    fn counts_columns_in_characters_and_keeps_tabs() {
+        let source = "\tprint \"é\" + 1;";
+        let output = diagnostic(7, 15).render("main.mg", source, false);
+
+        assert!(output.contains("main.mg:1:8"), "{output}");
+        assert!(output.ends_with("  | \t      ^^^^^^^\n"), "{output}");
+    }
+
+    #[test]
+    fn shows_the_help_note() {
+        let output = diagnostic(0, 1)
+            .with_help(Some("check the divisor".to_string()))
+            .render("main.mg", "1 / 0", false);
+
+        assert!(output.ends_with("  |\n  = help: check the divisor\n"), "{output}");
+    }
+
+    #[test]
+    fn survives_spans_from_other_text() {
+        // Inside the second 'é', then past the end altogether.
+        let source = "print \"éé\";";
+        let output = diagnostic(10, 20).render("main.mg", source, false);
+        assert!(output.contains("1 | print \"éé\";"), "{output}");
+
+        let output = diagnostic(40, 50).render("main.mg", source, false);
+        assert!(output.contains("1 | print \"éé\";"), "{output}");
+    }
+
+    #[test]
+    fn colors_only_when_asked() {
+        let plain = diagnostic(0, 1).render("main.mg", "1 / 0", false);
+        let colored = diagnostic(0, 1).render("main.mg", "1 / 0", true);
+
+        assert!(!plain.contains('\x1b'));
+        assert!(colored.contains(RED) && colored.contains(RESET));
+    }
+}
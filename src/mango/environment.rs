@@ -1,50 +1,203 @@
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::ops::Index;
+use std::rc::Rc;
 
+use super::error::RuntimeError;
 use super::literal::Literal;
+use super::span::Span;
 
-#[derive(Clone, Debug)]
+/// Where the resolver found a local variable: `depth` functions out from the
+/// one it is used in (0 for the running function itself), at `index` among
+/// that function's variables.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Slot {
+    pub depth: usize,
+    pub index: usize,
+}
+
+/// A single local variable. It is shared, so a closure and the scope that
+/// created it see (and mutate) the very same variable.
+pub type Variable = Rc<RefCell<Literal>>;
+
+/// The variables a closure can see: those in scope where it was declared,
+/// then those its declaring function could see, and so on outwards.
+#[derive(Debug, Default)]
+pub struct Captured {
+    variables: Vec<Variable>,
+    enclosing: Option<Rc<Captured>>,
+}
+
+/// The local variables of one function call, kept as a stack. A block adds
+/// its variables on top as they are declared and drops them when it ends, so
+/// entering and leaving one costs nothing by itself. The top level of a
+/// program has an environment too, for variables declared in its blocks.
+#[derive(Debug, Default)]
 pub struct Environment {
-    values: HashMap<String, Literal>,
-    pub enclosing: Option<Box<Environment>>,
+    values: Vec<Variable>,
+    enclosing: Option<Rc<Captured>>,
 }
 
 impl Environment {
     pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// An environment for a call to a closure that captured `enclosing`.
+    pub fn from_enclosing(enclosing: Rc<Captured>) -> Self {
         Self {
-            values: HashMap::new(),
-            enclosing: None,
+            values: Vec::new(),
+            enclosing: Some(enclosing),
         }
     }
 
-    pub fn from_enclosing(enclosing: Environment) -> Self {
-        Self {
-            values: HashMap::new(),
-            enclosing: Some(Box::new(enclosing)),
+    /// How many variables are in scope, to `truncate` back to when a block
+    /// ends.
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn truncate(&mut self, length: usize) {
+        self.values.truncate(length);
+    }
+
+    pub fn define(&mut self, value: Literal) -> Variable {
+        let variable = Rc::new(RefCell::new(value));
+        self.values.push(Rc::clone(&variable));
+
+        variable
+    }
+
+    /// What a closure declared right now gets to keep.
+    pub fn capture(&self) -> Rc<Captured> {
+        Rc::new(Captured {
+            variables: self.values.clone(),
+            enclosing: self.enclosing.clone(),
+        })
+    }
+
+    fn variable(&self, slot: Slot) -> Option<&Variable> {
+        if slot.depth == 0 {
+            return self.values.get(slot.index);
+        }
+
+        let mut captured = self.enclosing.as_ref()?;
+        for _ in 1..slot.depth {
+            captured = captured.enclosing.as_ref()?;
         }
+
+        captured.variables.get(slot.index)
     }
 
-    pub fn define(&mut self, name: String, value: Literal) {
-        self.values.insert(name, value);
+    pub fn access(&self, slot: Slot) -> Literal {
+        self.variable(slot)
+            .map_or(Literal::None, |variable| variable.borrow().clone())
     }
 
-    pub fn access(&mut self, name: &String) -> Option<&Literal> {
-        if let Some(value) = self.values.get(name) {
-            return Some(value);
-        } else if let Some(enclosing) = &mut self.enclosing {
-            return enclosing.access(name);
+    pub fn assign(&self, slot: Slot, value: Literal) {
+        if let Some(variable) = self.variable(slot) {
+            *variable.borrow_mut() = value;
         }
+    }
+}
+
+struct Global {
+    name: String,
+    value: Option<Literal>,
+    constant: bool,
+}
 
-        None
+/// The global variables, each in a numbered slot. A name gets its slot the
+/// first time it is seen, even before it is declared, and keeps it from then
+/// on, so later REPL inputs reach the globals of earlier ones. The resolver
+/// rejects assignments to constants it can see; these checks catch the rest,
+/// such as ones from an earlier REPL input.
+#[derive(Default)]
+pub struct Globals {
+    slots: HashMap<String, usize>,
+    globals: Vec<Global>,
+}
+
+impl Globals {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The slot of the global called `name`, added empty if it is new.
+    pub fn slot(&mut self, name: &str) -> usize {
+        if let Some(&slot) = self.slots.get(name) {
+            return slot;
+        }
+
+        self.globals.push(Global {
+            name: name.to_string(),
+            value: None,
+            constant: false,
+        });
+        self.slots.insert(name.to_string(), self.globals.len() - 1);
+
+        self.globals.len() - 1
     }
 
-    pub fn assign(&mut self, name: &String, value: Literal) -> Literal {
-        if self.values.contains_key(name) {
-            self.values.insert(name.clone(), value.clone());
-            value
-        } else if let Some(ref mut enclosing) = self.enclosing {
-            enclosing.assign(name, value)
-        } else {
-            panic!("Undefined variable '{}'.", name);
+    pub fn define(
+        &mut self,
+        slot: usize,
+        value: Literal,
+        constant: bool,
+        span: Span,
+    ) -> Result<(), RuntimeError> {
+        let global = &mut self.globals[slot];
+        if global.constant {
+            return Err(RuntimeError::new(
+                format!("Can't redeclare constant '{}'", global.name),
+                span,
+            ));
         }
+
+        global.value = Some(value);
+        global.constant = constant;
+
+        Ok(())
+    }
+
+    pub fn get(&self, slot: usize, span: Span) -> Result<Literal, RuntimeError> {
+        let global = &self.globals[slot];
+
+        global.value.clone().ok_or_else(|| {
+            RuntimeError::new(format!("Undefined variable '{}'", global.name), span)
+        })
+    }
+
+    pub fn assign(&mut self, slot: usize, value: Literal, span: Span) -> Result<(), RuntimeError> {
+        let global = &mut self.globals[slot];
+        if global.constant {
+            return Err(RuntimeError::new(
+                format!("Can't assign to constant '{}'", global.name),
+                span,
+            ));
+        }
+
+        match &mut global.value {
+            Some(current) => *current = value,
+            None => {
+                return Err(RuntimeError::new(
+                    format!("Undefined variable '{}'", global.name),
+                    span,
+                ))
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Index<&str> for Globals {
+    type Output = Literal;
+
+    fn index(&self, name: &str) -> &Literal {
+        self.globals[self.slots[name]]
+            .value
+            .as_ref()
+            .expect("the global is defined")
     }
 }
@@ -0,0 +1,160 @@
+use std::fmt::{self, Display, Formatter};
+
+use super::span::Span;
+
+#[derive(Clone, Debug)]
+pub struct LexError {
+    pub message: String,
+    pub span: Span,
+}
+
+impl LexError {
+    pub fn new(message: String, span: Span) -> Self {
+        Self { message, span }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ParseError {
+    pub message: String,
+    pub span: Span,
+    pub help: Option<String>,
+}
+
+impl ParseError {
+    pub fn new(message: String, span: Span) -> Self {
+        Self {
+            message,
+            span,
+            help: None,
+        }
+    }
+
+    pub fn with_help(mut self, help: String) -> Self {
+        self.help = Some(help);
+        self
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ResolveError {
+    pub message: String,
+    pub span: Span,
+    pub help: Option<String>,
+}
+
+impl ResolveError {
+    pub fn new(message: String, span: Span) -> Self {
+        Self {
+            message,
+            span,
+            help: None,
+        }
+    }
+
+    pub fn with_help(mut self, help: String) -> Self {
+        self.help = Some(help);
+        self
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RuntimeError {
+    pub message: String,
+    pub span: Span,
+}
+
+impl RuntimeError {
+    pub fn new(message: String, span: Span) -> Self {
+        Self { message, span }
+    }
+}
+
+/// Why an operator couldn't be applied to its operands. It has no location
+/// of its own: whoever applied the operator adds that with `at`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum OperatorError {
+    /// The operator isn't defined for the operands' types. `right` is `None`
+    /// for a unary operator.
+    Unsupported {
+        operator: &'static str,
+        left: &'static str,
+        right: Option<&'static str>,
+    },
+    DivisionByZero,
+    /// The operand is of the right type but has no result, like `(-1)!`, or
+    /// one too large to compute, like `2 ** 10_000_000_000`.
+    OutOfRange { operator: &'static str },
+}
+
+impl OperatorError {
+    pub fn at(self, span: Span) -> RuntimeError {
+        RuntimeError::new(self.to_string(), span)
+    }
+}
+
+impl Display for OperatorError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            OperatorError::Unsupported {
+                operator,
+                left,
+                right: Some(right),
+            } => write!(f, "Can't apply '{operator}' to {left} and {right}"),
+            OperatorError::Unsupported {
+                operator,
+                left,
+                right: None,
+            } => write!(f, "Can't apply '{operator}' to {left}"),
+            OperatorError::DivisionByZero => write!(f, "Division by zero"),
+            OperatorError::OutOfRange { operator } => {
+                write!(f, "Operand out of range for '{operator}'")
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum MangoError {
+    Lex(LexError),
+    Parse(ParseError),
+    Resolve(ResolveError),
+    Runtime(RuntimeError),
+}
+
+impl Display for MangoError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let (kind, message, span) = match self {
+            MangoError::Lex(error) => ("Lex", &error.message, error.span),
+            MangoError::Parse(error) => ("Parse", &error.message, error.span),
+            MangoError::Resolve(error) => ("Resolve", &error.message, error.span),
+            MangoError::Runtime(error) => ("Runtime", &error.message, error.span),
+        };
+
+        write!(f, "{kind} error [{}:{}]: {message}", span.line, span.column)
+    }
+}
+
+impl From<LexError> for MangoError {
+    fn from(error: LexError) -> Self {
+        MangoError::Lex(error)
+    }
+}
+
+impl From<ParseError> for MangoError {
+    fn from(error: ParseError) -> Self {
+        MangoError::Parse(error)
+    }
+}
+
+impl From<ResolveError> for MangoError {
+    fn from(error: ResolveError) -> Self {
+        MangoError::Resolve(error)
+    }
+}
+
+impl From<RuntimeError> for MangoError {
+    fn from(error: RuntimeError) -> Self {
+        MangoError::Runtime(error)
+    }
+}
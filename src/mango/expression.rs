@@ -1,4 +1,8 @@
+use std::cell::Cell;
+
+use super::environment::Slot;
 use super::literal::Literal;
+use super::span::Span;
 use super::token::Token;
 
 #[derive(Debug)]
@@ -7,20 +11,78 @@ pub enum Expression {
         left: Box<Expression>,
         operator: Token,
         right: Box<Expression>,
+        span: Span,
+    },
+    Logical {
+        left: Box<Expression>,
+        operator: Token,
+        right: Box<Expression>,
+        span: Span,
+    },
+    /// A chain of two or more comparisons, like `a < b <= c`. Each operand is
+    /// evaluated at most once, and the chain stops at the first that fails.
+    Comparison {
+        operands: Vec<Expression>,
+        operators: Vec<Token>,
+        span: Span,
     },
     Unary {
         operator: Token,
         right: Box<Expression>,
         is_prefix: bool,
+        span: Span,
+    },
+    Literal {
+        value: Literal,
+        span: Span,
+    },
+    /// `slot` is filled in by the resolver for local variables, and left
+    /// empty for globals. The tree-walker keeps the global's slot in `global`
+    /// once it has looked the name up.
+    Variable {
+        name: String,
+        slot: Cell<Option<Slot>>,
+        global: Cell<Option<usize>>,
+        span: Span,
     },
-    Literal(Literal),
-    Variable(String),
     Assignment {
         name: String,
+        slot: Cell<Option<Slot>>,
+        global: Cell<Option<usize>>,
         value: Box<Expression>,
+        span: Span,
     },
     Grouping {
         expression: Box<Expression>,
+        span: Span,
+    },
+    Call {
+        callee: Box<Expression>,
+        arguments: Vec<Expression>,
+        span: Span,
+    },
+    List {
+        elements: Vec<Expression>,
+        span: Span,
+    },
+    Interpolation {
+        parts: Vec<Expression>,
+        span: Span,
+    },
+    Map {
+        entries: Vec<(Expression, Expression)>,
+        span: Span,
+    },
+    Index {
+        target: Box<Expression>,
+        index: Box<Expression>,
+        span: Span,
+    },
+    IndexAssignment {
+        target: Box<Expression>,
+        index: Box<Expression>,
+        value: Box<Expression>,
+        span: Span,
     },
 }
 
@@ -31,25 +93,114 @@ impl Expression {
                 left,
                 operator,
                 right,
+                ..
             } => visitor.visit_binary(left, operator, right),
+            Expression::Logical {
+                left,
+                operator,
+                right,
+                ..
+            } => visitor.visit_logical(left, operator, right),
+            Expression::Comparison {
+                operands,
+                operators,
+                ..
+            } => visitor.visit_comparison(operands, operators),
             Expression::Unary {
                 operator,
                 right,
                 is_prefix,
+                ..
             } => visitor.visit_unary(operator, right, *is_prefix),
-            Expression::Literal(literal) => visitor.visit_literal(literal),
-            Expression::Variable(name) => visitor.visit_variable(name),
-            Expression::Assignment { name, value } => visitor.visit_assignment(name, value),
-            Expression::Grouping { expression } => visitor.visit_grouping(expression),
+            Expression::Literal { value, .. } => visitor.visit_literal(value),
+            Expression::Variable {
+                name,
+                slot,
+                global,
+                span,
+            } => visitor.visit_variable(name, slot, global, *span),
+            Expression::Assignment {
+                name,
+                slot,
+                global,
+                value,
+                span,
+            } => visitor.visit_assignment(name, slot, global, value, *span),
+            Expression::Grouping { expression, .. } => visitor.visit_grouping(expression),
+            Expression::Call {
+                callee,
+                arguments,
+                span,
+            } => visitor.visit_call(callee, arguments, *span),
+            Expression::List { elements, .. } => visitor.visit_list(elements),
+            Expression::Interpolation { parts, .. } => visitor.visit_interpolation(parts),
+            Expression::Map { entries, span } => visitor.visit_map(entries, *span),
+            Expression::Index {
+                target,
+                index,
+                span,
+            } => visitor.visit_index(target, index, *span),
+            Expression::IndexAssignment {
+                target,
+                index,
+                value,
+                span,
+            } => visitor.visit_index_assignment(target, index, value, *span),
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            Expression::Binary { span, .. }
+            | Expression::Logical { span, .. }
+            | Expression::Comparison { span, .. }
+            | Expression::Unary { span, .. }
+            | Expression::Literal { span, .. }
+            | Expression::Variable { span, .. }
+            | Expression::Assignment { span, .. }
+            | Expression::Grouping { span, .. }
+            | Expression::Call { span, .. }
+            | Expression::List { span, .. }
+            | Expression::Interpolation { span, .. }
+            | Expression::Map { span, .. }
+            | Expression::Index { span, .. }
+            | Expression::IndexAssignment { span, .. } => *span,
         }
     }
 }
 
 pub trait Visitor<T> {
     fn visit_binary(&mut self, left: &Expression, operator: &Token, right: &Expression) -> T;
+    fn visit_logical(&mut self, left: &Expression, operator: &Token, right: &Expression) -> T;
+    fn visit_comparison(&mut self, operands: &[Expression], operators: &[Token]) -> T;
     fn visit_unary(&mut self, operator: &Token, right: &Expression, is_prefix: bool) -> T;
     fn visit_literal(&mut self, literal: &Literal) -> T;
-    fn visit_variable(&mut self, name: &String) -> T;
-    fn visit_assignment(&mut self, name: &String, value: &Box<Expression>) -> T;
+    fn visit_variable(
+        &mut self,
+        name: &str,
+        slot: &Cell<Option<Slot>>,
+        global: &Cell<Option<usize>>,
+        span: Span,
+    ) -> T;
+    fn visit_assignment(
+        &mut self,
+        name: &str,
+        slot: &Cell<Option<Slot>>,
+        global: &Cell<Option<usize>>,
+        value: &Expression,
+        span: Span,
+    ) -> T;
     fn visit_grouping(&mut self, expression: &Expression) -> T;
+    fn visit_call(&mut self, callee: &Expression, arguments: &[Expression], span: Span) -> T;
+    fn visit_list(&mut self, elements: &[Expression]) -> T;
+    fn visit_interpolation(&mut self, parts: &[Expression]) -> T;
+    fn visit_map(&mut self, entries: &[(Expression, Expression)], span: Span) -> T;
+    fn visit_index(&mut self, target: &Expression, index: &Expression, span: Span) -> T;
+    fn visit_index_assignment(
+        &mut self,
+        target: &Expression,
+        index: &Expression,
+        value: &Expression,
+        span: Span,
+    ) -> T;
 }
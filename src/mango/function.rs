@@ -0,0 +1,61 @@
+use std::cmp::Ordering;
+use std::fmt::{self, Debug, Formatter};
+use std::rc::Rc;
+
+use super::environment::Captured;
+use super::statement::Statement;
+
+/// A user-defined function, created when its `fn` declaration runs. It keeps
+/// hold of the variables in scope where it was declared, so it can still reach
+/// (and update) them after that scope has been left.
+pub struct Function {
+    pub name: String,
+    pub parameters: Vec<String>,
+    pub body: Rc<Vec<Statement>>,
+    pub closure: Rc<Captured>,
+}
+
+impl Function {
+    pub fn new(
+        name: String,
+        parameters: Vec<String>,
+        body: Rc<Vec<Statement>>,
+        closure: Rc<Captured>,
+    ) -> Self {
+        Self {
+            name,
+            parameters,
+            body,
+            closure,
+        }
+    }
+
+    pub fn arity(&self) -> usize {
+        self.parameters.len()
+    }
+}
+
+// The closure usually contains the function itself, so printing it would
+// never terminate.
+impl Debug for Function {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Function({})", self.name)
+    }
+}
+
+// Functions are only ever equal to themselves, and have no ordering.
+impl PartialEq for Function {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl PartialOrd for Function {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        if self == other {
+            Some(Ordering::Equal)
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,715 @@
+use std::cmp::Ordering;
+use std::fmt::{self, Display, Formatter};
+
+/// The largest result, in bits, that `pow` and `factorial` will compute:
+/// about 79,000 decimal digits. Anything bigger would take seconds or more to
+/// compute and print, so they give up instead.
+const MAX_RESULT_BITS: u64 = 1 << 18;
+
+/// An exact integer. Values that fit in an `i64` are stored as one; anything
+/// larger is promoted to a `BigInt`, and results are demoted back whenever
+/// they fit again, so `Big` never holds a value in `i64` range.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum Integer {
+    Small(i64),
+    Big(BigInt),
+}
+
+impl Integer {
+    /// Parses digits (without sign or prefix) in the given radix.
+    pub fn parse(digits: &str, radix: u32) -> Option<Integer> {
+        if let Ok(value) = i64::from_str_radix(digits, radix) {
+            return Some(Integer::Small(value));
+        }
+
+        BigInt::parse(digits, radix).map(Integer::from_big)
+    }
+
+    fn from_big(value: BigInt) -> Integer {
+        match value.to_i64() {
+            Some(small) => Integer::Small(small),
+            None => Integer::Big(value),
+        }
+    }
+
+    fn to_big(&self) -> BigInt {
+        match self {
+            Integer::Small(value) => BigInt::from_i64(*value),
+            Integer::Big(value) => value.clone(),
+        }
+    }
+
+    pub fn to_i64(&self) -> Option<i64> {
+        match self {
+            Integer::Small(value) => Some(*value),
+            Integer::Big(_) => None,
+        }
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        match self {
+            Integer::Small(value) => *value as f64,
+            Integer::Big(value) => value.to_f64(),
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        matches!(self, Integer::Small(0))
+    }
+
+    pub fn is_negative(&self) -> bool {
+        match self {
+            Integer::Small(value) => *value < 0,
+            Integer::Big(value) => value.negative,
+        }
+    }
+
+    fn is_odd(&self) -> bool {
+        match self {
+            Integer::Small(value) => value & 1 == 1,
+            Integer::Big(value) => value.magnitude.first().is_some_and(|limb| limb & 1 == 1),
+        }
+    }
+
+    /// The base 2 logarithm of the magnitude, rounded up for a `Big`.
+    fn log2(&self) -> f64 {
+        match self {
+            Integer::Small(value) => (value.unsigned_abs() as f64).log2(),
+            Integer::Big(value) => value.bits() as f64,
+        }
+    }
+
+    /// Runs the fast `i64` operation when both operands are small, falling back
+    /// to `BigInt` when either isn't or the fast path overflows.
+    fn apply(
+        &self,
+        other: &Integer,
+        small: impl Fn(i64, i64) -> Option<i64>,
+        big: impl Fn(&BigInt, &BigInt) -> BigInt,
+    ) -> Integer {
+        if let (Integer::Small(a), Integer::Small(b)) = (self, other) {
+            if let Some(result) = small(*a, *b) {
+                return Integer::Small(result);
+            }
+        }
+
+        Integer::from_big(big(&self.to_big(), &other.to_big()))
+    }
+
+    pub fn add(&self, other: &Integer) -> Integer {
+        self.apply(other, i64::checked_add, BigInt::add)
+    }
+
+    pub fn sub(&self, other: &Integer) -> Integer {
+        self.apply(other, i64::checked_sub, BigInt::sub)
+    }
+
+    pub fn mul(&self, other: &Integer) -> Integer {
+        self.apply(other, i64::checked_mul, BigInt::mul)
+    }
+
+    pub fn neg(&self) -> Integer {
+        Integer::Small(0).sub(self)
+    }
+
+    /// Division rounding towards negative infinity. `None` when dividing by
+    /// zero.
+    pub fn floor_div(&self, other: &Integer) -> Option<Integer> {
+        if other.is_zero() {
+            return None;
+        }
+
+        Some(self.apply(
+            other,
+            |a, b| {
+                let quotient = a.checked_div(b)?;
+                if a % b != 0 && (a < 0) != (b < 0) {
+                    quotient.checked_sub(1)
+                } else {
+                    Some(quotient)
+                }
+            },
+            |a, b| a.floor_div_rem(b).0,
+        ))
+    }
+
+    /// The remainder matching `floor_div`, which takes the sign of the
+    /// divisor. `None` when dividing by zero.
+    pub fn rem(&self, other: &Integer) -> Option<Integer> {
+        if other.is_zero() {
+            return None;
+        }
+
+        Some(self.apply(
+            other,
+            |a, b| a.checked_rem_euclid(b).map(|r| if r != 0 && b < 0 { r + b } else { r }),
+            |a, b| a.floor_div_rem(b).1,
+        ))
+    }
+
+    /// Raises to a non-negative power. `None` for a negative exponent or a
+    /// result over `MAX_RESULT_BITS`.
+    pub fn pow(&self, exponent: &Integer) -> Option<Integer> {
+        if exponent.is_negative() {
+            return None;
+        }
+
+        // These stay small however large the exponent.
+        match self {
+            Integer::Small(0) if exponent.is_zero() => return Some(Integer::Small(1)),
+            Integer::Small(0) | Integer::Small(1) => return Some(self.clone()),
+            Integer::Small(-1) if exponent.is_odd() => return Some(Integer::Small(-1)),
+            Integer::Small(-1) => return Some(Integer::Small(1)),
+            _ => {}
+        }
+
+        // The result takes about `exponent * log2(base)` bits.
+        let exponent = u32::try_from(exponent.to_i64()?).ok()?;
+        if exponent as f64 * self.log2() > MAX_RESULT_BITS as f64 {
+            return None;
+        }
+
+        if let Integer::Small(base) = self {
+            if let Some(result) = base.checked_pow(exponent) {
+                return Some(Integer::Small(result));
+            }
+        }
+
+        Some(Integer::from_big(self.to_big().pow(exponent)))
+    }
+
+    /// `n!`, for non-negative `n`. `None` for negative `n` or a result over
+    /// `MAX_RESULT_BITS`.
+    pub fn factorial(&self) -> Option<Integer> {
+        let n = u32::try_from(self.to_i64()?).ok()?;
+
+        // Stirling's approximation of log2(n!), which is close enough to
+        // tell whether the result fits.
+        if n > 1 {
+            let n = n as f64;
+            let bits = n * (n / std::f64::consts::E).log2()
+                + 0.5 * (std::f64::consts::TAU * n).log2();
+            if bits > MAX_RESULT_BITS as f64 {
+                return None;
+            }
+        }
+
+        let mut result = Integer::Small(1);
+        for i in 2..=n {
+            result = result.mul(&Integer::Small(i as i64));
+        }
+
+        Some(result)
+    }
+}
+
+impl PartialOrd for Integer {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Integer {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Integer::Small(a), Integer::Small(b)) => a.cmp(b),
+            _ => self.to_big().cmp(&other.to_big()),
+        }
+    }
+}
+
+impl Display for Integer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Integer::Small(value) => write!(f, "{}", value),
+            Integer::Big(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+/// An arbitrary-precision signed integer: a sign and a little-endian
+/// magnitude in base 2^32, with no trailing zero limbs. Zero is never
+/// negative.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct BigInt {
+    negative: bool,
+    magnitude: Vec<u32>,
+}
+
+impl BigInt {
+    fn new(negative: bool, mut magnitude: Vec<u32>) -> Self {
+        while magnitude.last() == Some(&0) {
+            magnitude.pop();
+        }
+
+        Self {
+            negative: negative && !magnitude.is_empty(),
+            magnitude,
+        }
+    }
+
+    fn from_i64(value: i64) -> Self {
+        let abs = value.unsigned_abs();
+        BigInt::new(value < 0, vec![abs as u32, (abs >> 32) as u32])
+    }
+
+    fn parse(digits: &str, radix: u32) -> Option<Self> {
+        if digits.is_empty() {
+            return None;
+        }
+
+        let mut magnitude = Vec::new();
+        for c in digits.chars() {
+            let digit = c.to_digit(radix)?;
+            magnitude = mul_small_add(&magnitude, radix, digit);
+        }
+
+        Some(BigInt::new(false, magnitude))
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        if self.magnitude.len() > 2 {
+            return None;
+        }
+
+        let abs = self
+            .magnitude
+            .iter()
+            .rev()
+            .fold(0u64, |acc, limb| (acc << 32) | *limb as u64);
+
+        if self.negative {
+            0i64.checked_sub_unsigned(abs)
+        } else {
+            i64::try_from(abs).ok()
+        }
+    }
+
+    fn to_f64(&self) -> f64 {
+        let abs = self
+            .magnitude
+            .iter()
+            .rev()
+            .fold(0f64, |acc, limb| acc * 4294967296.0 + *limb as f64);
+
+        if self.negative {
+            -abs
+        } else {
+            abs
+        }
+    }
+
+    fn bits(&self) -> u64 {
+        match self.magnitude.last() {
+            Some(top) => self.magnitude.len() as u64 * 32 - top.leading_zeros() as u64,
+            None => 0,
+        }
+    }
+
+    fn add(&self, other: &BigInt) -> BigInt {
+        if self.negative == other.negative {
+            return BigInt::new(self.negative, add_magnitudes(&self.magnitude, &other.magnitude));
+        }
+
+        match compare_magnitudes(&self.magnitude, &other.magnitude) {
+            Ordering::Less => BigInt::new(
+                other.negative,
+                sub_magnitudes(&other.magnitude, &self.magnitude),
+            ),
+            _ => BigInt::new(
+                self.negative,
+                sub_magnitudes(&self.magnitude, &other.magnitude),
+            ),
+        }
+    }
+
+    fn sub(&self, other: &BigInt) -> BigInt {
+        self.add(&BigInt::new(!other.negative, other.magnitude.clone()))
+    }
+
+    fn mul(&self, other: &BigInt) -> BigInt {
+        BigInt::new(
+            self.negative != other.negative,
+            mul_magnitudes(&self.magnitude, &other.magnitude),
+        )
+    }
+
+    fn pow(&self, mut exponent: u32) -> BigInt {
+        let mut result = BigInt::from_i64(1);
+        let mut base = self.clone();
+
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = result.mul(&base);
+            }
+            exponent >>= 1;
+            if exponent > 0 {
+                base = base.mul(&base);
+            }
+        }
+
+        result
+    }
+
+    /// Floored division and remainder. `other` must not be zero.
+    fn floor_div_rem(&self, other: &BigInt) -> (BigInt, BigInt) {
+        let (quotient, remainder) = div_rem_magnitudes(&self.magnitude, &other.magnitude);
+        let quotient = BigInt::new(self.negative != other.negative, quotient);
+        let remainder = BigInt::new(self.negative, remainder);
+
+        // Truncated division rounds towards zero; step down when the exact
+        // result would have been negative.
+        if !remainder.magnitude.is_empty() && self.negative != other.negative {
+            (quotient.sub(&BigInt::from_i64(1)), remainder.add(other))
+        } else {
+            (quotient, remainder)
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => compare_magnitudes(&self.magnitude, &other.magnitude),
+            (true, true) => compare_magnitudes(&other.magnitude, &self.magnitude),
+        }
+    }
+}
+
+impl Display for BigInt {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.magnitude.is_empty() {
+            return write!(f, "0");
+        }
+
+        // Peel off nine decimal digits at a time, least significant first.
+        let mut chunks = Vec::new();
+        let mut magnitude = self.magnitude.clone();
+        while !magnitude.is_empty() {
+            let (quotient, remainder) = div_rem_small(&magnitude, 1_000_000_000);
+            chunks.push(remainder);
+            magnitude = quotient;
+        }
+
+        if self.negative {
+            write!(f, "-")?;
+        }
+        write!(f, "{}", chunks.pop().unwrap_or(0))?;
+        for chunk in chunks.iter().rev() {
+            write!(f, "{:09}", chunk)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn trim(mut magnitude: Vec<u32>) -> Vec<u32> {
+    while magnitude.last() == Some(&0) {
+        magnitude.pop();
+    }
+    magnitude
+}
+
+fn compare_magnitudes(a: &[u32], b: &[u32]) -> Ordering {
+    a.len()
+        .cmp(&b.len())
+        .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(a.len().max(b.len()) + 1);
+    let mut carry = 0u64;
+
+    for i in 0..a.len().max(b.len()) {
+        let sum = *a.get(i).unwrap_or(&0) as u64 + *b.get(i).unwrap_or(&0) as u64 + carry;
+        result.push(sum as u32);
+        carry = sum >> 32;
+    }
+    result.push(carry as u32);
+
+    trim(result)
+}
+
+/// `a - b`, where `a >= b`.
+fn sub_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(a.len());
+    let mut borrow = 0i64;
+
+    for (i, limb) in a.iter().enumerate() {
+        let mut difference = *limb as i64 - *b.get(i).unwrap_or(&0) as i64 - borrow;
+        borrow = if difference < 0 {
+            difference += 1 << 32;
+            1
+        } else {
+            0
+        };
+        result.push(difference as u32);
+    }
+
+    trim(result)
+}
+
+fn mul_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = vec![0u32; a.len() + b.len()];
+
+    for (i, x) in a.iter().enumerate() {
+        let mut carry = 0u64;
+        for (j, y) in b.iter().enumerate() {
+            let product = *x as u64 * *y as u64 + result[i + j] as u64 + carry;
+            result[i + j] = product as u32;
+            carry = product >> 32;
+        }
+        result[i + b.len()] = carry as u32;
+    }
+
+    trim(result)
+}
+
+fn mul_small_add(a: &[u32], factor: u32, addend: u32) -> Vec<u32> {
+    let mut result = Vec::with_capacity(a.len() + 1);
+    let mut carry = addend as u64;
+
+    for limb in a {
+        let product = *limb as u64 * factor as u64 + carry;
+        result.push(product as u32);
+        carry = product >> 32;
+    }
+    result.push(carry as u32);
+
+    trim(result)
+}
+
+fn div_rem_small(a: &[u32], divisor: u32) -> (Vec<u32>, u32) {
+    let mut quotient = vec![0u32; a.len()];
+    let mut remainder = 0u64;
+
+    for i in (0..a.len()).rev() {
+        let current = (remainder << 32) | a[i] as u64;
+        quotient[i] = (current / divisor as u64) as u32;
+        remainder = current % divisor as u64;
+    }
+
+    (trim(quotient), remainder as u32)
+}
+
+/// Truncated division of magnitudes by binary long division. Slow for huge
+/// operands, but simple, and plenty for numbers a script will print.
+fn div_rem_magnitudes(a: &[u32], b: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    if compare_magnitudes(a, b) == Ordering::Less {
+        return (Vec::new(), a.to_vec());
+    }
+    if b.len() == 1 {
+        let (quotient, remainder) = div_rem_small(a, b[0]);
+        return (quotient, trim(vec![remainder]));
+    }
+
+    let mut quotient = vec![0u32; a.len()];
+    let mut remainder: Vec<u32> = Vec::new();
+
+    for i in (0..a.len() * 32).rev() {
+        let bit = (a[i / 32] >> (i % 32)) & 1;
+        remainder = mul_small_add(&remainder, 2, bit);
+
+        if compare_magnitudes(&remainder, b) != Ordering::Less {
+            remainder = sub_magnitudes(&remainder, b);
+            quotient[i / 32] |= 1 << (i % 32);
+        }
+    }
+
+    (trim(quotient), remainder)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Values around the `i64` limits and the limb boundaries, plus some
+    /// beyond `i64` range that still fit comfortably in an `i128`.
+    const VALUES: [i128; 18] = [
+        i64::MIN as i128 - (1 << 40),
+        i64::MIN as i128 - 1,
+        i64::MIN as i128,
+        i64::MIN as i128 + 1,
+        -(1 << 32) - 1,
+        -(1 << 32),
+        -7,
+        -1,
+        0,
+        1,
+        2,
+        7,
+        1 << 32,
+        (1 << 32) + 1,
+        i64::MAX as i128 - 1,
+        i64::MAX as i128,
+        i64::MAX as i128 + 1,
+        i64::MAX as i128 + (1 << 40),
+    ];
+
+    fn integer(value: i128) -> Integer {
+        let magnitude = Integer::parse(&value.unsigned_abs().to_string(), 10).unwrap();
+        if value < 0 {
+            magnitude.neg()
+        } else {
+            magnitude
+        }
+    }
+
+    /// Checks the value and that it is stored as `Small` exactly when it fits.
+    fn check(result: Integer, expected: i128) {
+        assert_eq!(result.to_string(), expected.to_string());
+        assert_eq!(
+            matches!(result, Integer::Small(_)),
+            i64::try_from(expected).is_ok(),
+            "{expected} is stored as {result:?}"
+        );
+    }
+
+    fn floor_div(a: i128, b: i128) -> i128 {
+        let quotient = a / b;
+        if a % b != 0 && (a < 0) != (b < 0) {
+            quotient - 1
+        } else {
+            quotient
+        }
+    }
+
+    #[test]
+    fn add_and_sub_across_the_i64_limits() {
+        for a in VALUES {
+            for b in VALUES {
+                check(integer(a).add(&integer(b)), a + b);
+                check(integer(a).sub(&integer(b)), a - b);
+            }
+        }
+    }
+
+    #[test]
+    fn mul_across_the_i64_limits() {
+        for a in VALUES {
+            for b in VALUES {
+                // Keep the product within `i128`.
+                if a.unsigned_abs() <= 1 << 64 && b.unsigned_abs() <= 1 << 64 {
+                    check(integer(a).mul(&integer(b)), a * b);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn neg_of_the_smallest_i64_is_promoted() {
+        check(integer(i64::MIN as i128).neg(), -(i64::MIN as i128));
+        check(integer(-(i64::MIN as i128)).neg(), i64::MIN as i128);
+    }
+
+    #[test]
+    fn floor_div_and_rem_for_every_sign() {
+        for a in VALUES {
+            for b in VALUES.into_iter().filter(|b| *b != 0) {
+                let quotient = floor_div(a, b);
+                check(integer(a).floor_div(&integer(b)).unwrap(), quotient);
+                check(integer(a).rem(&integer(b)).unwrap(), a - b * quotient);
+            }
+        }
+    }
+
+    #[test]
+    fn floor_div_and_rem_round_down() {
+        let cases = [(7, 2, 3, 1), (-7, 2, -4, 1), (7, -2, -4, -1), (-7, -2, 3, -1)];
+
+        for (a, b, quotient, remainder) in cases {
+            check(integer(a).floor_div(&integer(b)).unwrap(), quotient);
+            check(integer(a).rem(&integer(b)).unwrap(), remainder);
+        }
+    }
+
+    #[test]
+    fn smallest_i64_divided_by_minus_one() {
+        let min = integer(i64::MIN as i128);
+        check(min.floor_div(&integer(-1)).unwrap(), -(i64::MIN as i128));
+        check(min.rem(&integer(-1)).unwrap(), 0);
+    }
+
+    #[test]
+    fn division_by_zero() {
+        for a in VALUES {
+            assert_eq!(integer(a).floor_div(&integer(0)), None);
+            assert_eq!(integer(a).rem(&integer(0)), None);
+        }
+    }
+
+    #[test]
+    fn big_results_are_demoted_when_they_fit_again() {
+        let big = integer(i64::MAX as i128 + 10);
+        assert!(matches!(big, Integer::Big(_)));
+
+        check(big.sub(&integer(20)), i64::MAX as i128 - 10);
+        check(big.floor_div(&big).unwrap(), 1);
+        check(big.mul(&integer(0)), 0);
+    }
+
+    #[test]
+    fn parse_in_every_radix() {
+        for value in VALUES.into_iter().filter(|value| *value >= 0) {
+            let value = value as u128;
+            let digits = [
+                (2, format!("{value:b}")),
+                (8, format!("{value:o}")),
+                (10, format!("{value}")),
+                (16, format!("{value:x}")),
+            ];
+
+            for (radix, digits) in digits {
+                let parsed = Integer::parse(&digits, radix).unwrap();
+                check(parsed, value as i128);
+            }
+        }
+    }
+
+    #[test]
+    fn display_round_trips_for_large_values() {
+        let big = Integer::parse(&format!("1{}", "0".repeat(200)), 2).unwrap();
+        assert_eq!(Integer::Small(2).pow(&Integer::Small(200)), Some(big.clone()));
+
+        let printed = big.to_string();
+        assert_eq!(
+            printed,
+            "1606938044258990275541962092341162602522202993782792835301376"
+        );
+        assert_eq!(Integer::parse(&printed, 10), Some(big.clone()));
+        assert_eq!(big.neg().to_string(), format!("-{printed}"));
+
+        // Chunks of nine digits must keep their leading zeros.
+        let padded = format!("1{}1", "0".repeat(30));
+        assert_eq!(Integer::parse(&padded, 10).unwrap().to_string(), padded);
+    }
+
+    #[test]
+    fn pow_and_factorial() {
+        check(integer(-2).pow(&integer(63)).unwrap(), i64::MIN as i128);
+        check(integer(2).pow(&integer(64)).unwrap(), 1 << 64);
+        check(integer(-1).pow(&integer(i64::MAX as i128 + 1)).unwrap(), 1);
+        check(integer(0).pow(&integer(0)).unwrap(), 1);
+        assert_eq!(integer(2).pow(&integer(-1)), None);
+
+        check(integer(20).factorial().unwrap(), 2432902008176640000);
+        check(integer(21).factorial().unwrap(), 51090942171709440000);
+        assert_eq!(integer(-1).factorial(), None);
+    }
+
+    #[test]
+    fn pow_and_factorial_refuse_huge_results() {
+        assert_eq!(integer(3).pow(&integer(100_000_000)), None);
+        assert_eq!(integer(100_000_000).factorial(), None);
+        assert_eq!(integer(100_000).factorial(), None);
+        assert!(integer(1000).factorial().is_some());
+    }
+}
@@ -1,140 +1,853 @@
-use super::environment::Environment;
+use std::cell::{Cell, RefCell};
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+use super::environment::{Environment, Globals, Slot};
+use super::error::RuntimeError;
 use super::expression::{self, Expression};
+use super::function::Function;
+use super::map::Map;
 use super::statement::{self, Statement};
 
-use super::literal::{Fac, Literal, Pow};
+use super::literal::{Fac, FloorDiv, Literal, Pow};
+use super::span::Span;
+use super::token::Token;
 use super::token_type::TokenType;
 
+/// Deep enough for reasonable recursion, shallow enough that runaway
+/// recursion is reported before it overflows the interpreter's own stack,
+/// which needs `STACK_SIZE` for this many calls.
+pub const MAX_CALL_DEPTH: usize = 2048;
+
+/// The native stack to run the interpreter on. Each call nests several Rust
+/// frames, so the default main thread stack would only fit a few hundred.
+pub const STACK_SIZE: usize = 128 * 1024 * 1024;
+
+/// How control leaves a statement: on to the next one, out of or on to the
+/// next iteration of the enclosing loop, or out of the enclosing function with
+/// a value.
+pub enum Flow {
+    Next,
+    Break,
+    Continue,
+    Return(Literal),
+}
+
+/// Runs a resolved program by walking its syntax tree. Locals live in the
+/// running call's environment and are reached through the slots the resolver
+/// assigned; globals are looked up by name the first time each use of one
+/// runs, so they can be used before they are declared and carry over between
+/// REPL inputs.
 pub struct Interpreter {
     environment: Environment,
+    globals: Globals,
+    /// How many local scopes are open in the running call. Declarations made
+    /// outside of any are globals.
+    scopes: usize,
+    call_depth: usize,
 }
 
 impl Interpreter {
     pub fn new() -> Self {
         Self {
             environment: Environment::new(),
+            globals: Globals::new(),
+            scopes: 0,
+            call_depth: 0,
+        }
+    }
+
+    pub fn interpret(&mut self, statement: Statement) -> Result<(), RuntimeError> {
+        statement.accept(self)?;
+
+        Ok(())
+    }
+
+    /// Runs `run` in a new local scope, dropping the variables it declared
+    /// afterwards.
+    fn in_scope<T>(&mut self, run: impl FnOnce(&mut Self) -> T) -> T {
+        let length = self.environment.len();
+        self.scopes += 1;
+
+        // Hold on to the result until the scope is closed, so an error or
+        // early exit doesn't leave its variables behind.
+        let result = run(self);
+
+        self.scopes -= 1;
+        self.environment.truncate(length);
+
+        result
+    }
+
+    /// Declares a variable in the current scope, as a global if no local
+    /// scope is open.
+    fn define(
+        &mut self,
+        name: &str,
+        value: Literal,
+        constant: bool,
+        span: Span,
+    ) -> Result<(), RuntimeError> {
+        if self.scopes > 0 {
+            self.environment.define(value);
+            return Ok(());
+        }
+
+        let slot = self.globals.slot(name);
+        self.globals.define(slot, value, constant, span)
+    }
+
+    /// The slot of the global `name`, kept in `cached` so each use of it only
+    /// looks the name up once.
+    fn global(&mut self, name: &str, cached: &Cell<Option<usize>>) -> usize {
+        match cached.get() {
+            Some(slot) => slot,
+            None => {
+                let slot = self.globals.slot(name);
+                cached.set(Some(slot));
+                slot
+            }
+        }
+    }
+
+    fn execute_all(&mut self, statements: &[Statement]) -> Result<Flow, RuntimeError> {
+        for statement in statements {
+            match statement.accept(self)? {
+                Flow::Next => {}
+                flow => return Ok(flow),
+            }
+        }
+
+        Ok(Flow::Next)
+    }
+
+    fn execute_loop(
+        &mut self,
+        condition: Option<&Expression>,
+        increment: Option<&Expression>,
+        body: &Statement,
+    ) -> Result<Flow, RuntimeError> {
+        loop {
+            if let Some(condition) = condition {
+                if !condition.accept(self)?.is_truthy() {
+                    break;
+                }
+            }
+
+            match body.accept(self)? {
+                Flow::Break => break,
+                Flow::Next | Flow::Continue => {}
+                flow @ Flow::Return(_) => return Ok(flow),
+            }
+
+            if let Some(increment) = increment {
+                increment.accept(self)?;
+            }
+        }
+
+        Ok(Flow::Next)
+    }
+
+    fn call(
+        &mut self,
+        function: &Function,
+        arguments: Vec<Literal>,
+        span: Span,
+    ) -> Result<Literal, RuntimeError> {
+        if arguments.len() != function.arity() {
+            return Err(RuntimeError::new(
+                format!(
+                    "'{}' expects {} argument(s) but got {}",
+                    function.name,
+                    function.arity(),
+                    arguments.len()
+                ),
+                span,
+            ));
+        }
+
+        if self.call_depth >= MAX_CALL_DEPTH {
+            return Err(RuntimeError::new(
+                format!("Stack overflow calling '{}'", function.name),
+                span,
+            ));
+        }
+
+        let mut environment = Environment::from_enclosing(Rc::clone(&function.closure));
+        for argument in arguments {
+            environment.define(argument);
+        }
+
+        // The body runs in the scope of the parameters.
+        let previous = std::mem::replace(&mut self.environment, environment);
+        let scopes = std::mem::replace(&mut self.scopes, 1);
+        self.call_depth += 1;
+
+        let result = self.execute_all(&function.body);
+
+        self.call_depth -= 1;
+        self.scopes = scopes;
+        self.environment = previous;
+
+        match result? {
+            Flow::Return(value) => Ok(value),
+            _ => Ok(Literal::None),
+        }
+    }
+}
+
+/// Resolves `index` against a list of `length` elements. Negative indices
+/// count back from the end, so `-1` is the last element.
+fn list_index(index: &Literal, length: usize, span: Span) -> Result<usize, RuntimeError> {
+    let integer = match index {
+        Literal::Integer(n) => n,
+        other => {
+            return Err(RuntimeError::new(
+                format!("List index must be an integer, not {}", other),
+                span,
+            ))
+        }
+    };
+
+    // An index too big for an i64 is out of bounds of any list.
+    let resolved = integer.to_i64().map(|index| {
+        if index < 0 {
+            index + length as i64
+        } else {
+            index
+        }
+    });
+
+    match resolved {
+        Some(resolved) if resolved >= 0 && resolved < length as i64 => Ok(resolved as usize),
+        _ => Err(RuntimeError::new(
+            format!(
+                "Index {} out of bounds for list of length {}",
+                integer, length
+            ),
+            span,
+        )),
+    }
+}
+
+/// Reads `target[index]` from a list or map.
+pub fn get_index(target: Literal, index: &Literal, span: Span) -> Result<Literal, RuntimeError> {
+    match target {
+        Literal::List(elements) => {
+            let elements = elements.borrow();
+            let index = list_index(index, elements.len(), span)?;
+
+            Ok(elements[index].clone())
         }
+        Literal::Map(map) => match map.borrow().get(index) {
+            Some(value) => Ok(value.clone()),
+            None => Err(RuntimeError::new(
+                format!("Key '{}' not found in map", index),
+                span,
+            )),
+        },
+        other => Err(RuntimeError::new(
+            format!("Cannot index into a {}", other.type_name()),
+            span,
+        )),
     }
+}
 
-    pub fn interpret(&mut self, statement: Statement) {
-        statement.accept(self)
+/// Stores `value` at `target[index]` in a list or map, returning `value`.
+pub fn set_index(
+    target: Literal,
+    index: Literal,
+    value: Literal,
+    span: Span,
+) -> Result<Literal, RuntimeError> {
+    match target {
+        Literal::List(elements) => {
+            let mut elements = elements.borrow_mut();
+            let index = list_index(&index, elements.len(), span)?;
+            elements[index] = value.clone();
+
+            Ok(value)
+        }
+        Literal::Map(map) => {
+            if !map.borrow_mut().insert(index.clone(), value.clone()) {
+                return Err(RuntimeError::new(
+                    format!("A {} can't be used as a map key", index.type_name()),
+                    span,
+                ));
+            }
+
+            Ok(value)
+        }
+        other => Err(RuntimeError::new(
+            format!("Cannot index into a {}", other.type_name()),
+            span,
+        )),
     }
 }
 
-impl expression::Visitor<Literal> for Interpreter {
+/// The items a `for in` loop visits. This is a snapshot, so the loop body can
+/// freely change the collection.
+pub fn iterate(iterable: Literal, span: Span) -> Result<Vec<Literal>, RuntimeError> {
+    match iterable {
+        Literal::List(elements) => Ok(elements.borrow().clone()),
+        Literal::Map(map) => Ok(map.borrow().keys().cloned().collect()),
+        Literal::String(s) => Ok(s.chars().map(|c| Literal::String(c.to_string())).collect()),
+        other => Err(RuntimeError::new(
+            format!("Cannot iterate over a {}", other.type_name()),
+            span,
+        )),
+    }
+}
+
+/// Applies the arithmetic or comparison `operator` to two evaluated operands.
+/// `span` covers the whole operation, for errors.
+fn binary(
+    left: Literal,
+    operator: &Token,
+    right: Literal,
+    span: Span,
+) -> Result<Literal, RuntimeError> {
+    let value = match operator.kind {
+        TokenType::StarStar => left.pow(right),
+        TokenType::Star => left * right,
+        TokenType::Slash => left / right,
+        TokenType::TildeSlash => left.floor_div(right),
+        TokenType::Percent => left % right,
+        TokenType::Plus => left + right,
+        TokenType::Minus => left - right,
+
+        TokenType::Less => left.order("<", &right, Ordering::is_lt),
+        TokenType::LessEqual => left.order("<=", &right, Ordering::is_le),
+        TokenType::Greater => left.order(">", &right, Ordering::is_gt),
+        TokenType::GreaterEqual => left.order(">=", &right, Ordering::is_ge),
+        TokenType::EqualEqual => Ok(Literal::Boolean(left == right)),
+        TokenType::BangEqual => Ok(Literal::Boolean(left != right)),
+
+        kind => {
+            return Err(RuntimeError::new(
+                format!("Unsupported binary operator {:?}", kind),
+                operator.span,
+            ))
+        }
+    };
+
+    value.map_err(|error| error.at(span))
+}
+
+impl expression::Visitor<Result<Literal, RuntimeError>> for Interpreter {
     fn visit_binary(
         &mut self,
         left: &Expression,
-        operator: &super::token::Token,
+        operator: &Token,
         right: &Expression,
-    ) -> Literal {
-        let left = left.accept(self);
-        let right = right.accept(self);
+    ) -> Result<Literal, RuntimeError> {
+        let span = left.span().to(right.span());
+        let left = left.accept(self)?;
+        let right = right.accept(self)?;
 
-        match operator.kind {
-            TokenType::StarStar => left.pow(right),
-            TokenType::Star => left * right,
-            TokenType::Slash => left / right,
-            TokenType::Plus => left + right,
-            TokenType::Minus => left - right,
+        binary(left, operator, right, span)
+    }
+
+    fn visit_comparison(
+        &mut self,
+        operands: &[Expression],
+        operators: &[Token],
+    ) -> Result<Literal, RuntimeError> {
+        let mut left = operands[0].accept(self)?;
+
+        for (i, operator) in operators.iter().enumerate() {
+            let span = operands[i].span().to(operands[i + 1].span());
+            let right = operands[i + 1].accept(self)?;
+            if !binary(left, operator, right.clone(), span)?.is_truthy() {
+                return Ok(Literal::Boolean(false));
+            }
+            left = right;
+        }
+
+        Ok(Literal::Boolean(true))
+    }
+
+    fn visit_logical(
+        &mut self,
+        left: &Expression,
+        operator: &Token,
+        right: &Expression,
+    ) -> Result<Literal, RuntimeError> {
+        let left = left.accept(self)?;
 
-            TokenType::Less => Literal::Boolean(left < right),
-            TokenType::LessEqual => Literal::Boolean(left <= right),
-            TokenType::Greater => Literal::Boolean(left > right),
-            TokenType::GreaterEqual => Literal::Boolean(left >= right),
-            TokenType::EqualEqual => Literal::Boolean(left == right),
+        // Short-circuit: the right operand is only evaluated when the left one
+        // doesn't already decide the result.
+        match operator.kind {
+            TokenType::Or if left.is_truthy() => Ok(left),
+            TokenType::And if !left.is_truthy() => Ok(left),
+            TokenType::Or | TokenType::And => right.accept(self),
 
-            _ => panic!("Unsupported binary operator"),
+            kind => Err(RuntimeError::new(
+                format!("Unsupported logical operator {:?}", kind),
+                operator.span,
+            )),
         }
     }
 
     fn visit_unary(
         &mut self,
-        operator: &super::token::Token,
+        operator: &Token,
         right: &Expression,
         is_prefix: bool,
-    ) -> Literal {
-        let right = right.accept(self);
+    ) -> Result<Literal, RuntimeError> {
+        let span = if is_prefix {
+            operator.span.to(right.span())
+        } else {
+            right.span().to(operator.span)
+        };
+        let right = right.accept(self)?;
 
-        match (operator.kind, is_prefix) {
-            (TokenType::Bang, true) => !right,
+        let value = match (operator.kind, is_prefix) {
+            (TokenType::Bang, true) => Ok(!right),
             (TokenType::Minus, true) => -right,
 
             (TokenType::Bang, false) => right.fac(),
 
-            _ => panic!("Unsupported unary operator"),
-        }
+            (kind, _) => {
+                return Err(RuntimeError::new(
+                    format!("Unsupported unary operator {:?}", kind),
+                    operator.span,
+                ))
+            }
+        };
+
+        value.map_err(|error| error.at(span))
     }
 
-    fn visit_variable(&mut self, name: &String) -> Literal {
-        if let Some(variable) = self.environment.access(name).clone() {
-            return variable.clone();
+    fn visit_variable(
+        &mut self,
+        name: &str,
+        slot: &Cell<Option<Slot>>,
+        global: &Cell<Option<usize>>,
+        span: Span,
+    ) -> Result<Literal, RuntimeError> {
+        if let Some(slot) = slot.get() {
+            return Ok(self.environment.access(slot));
         }
 
-        Literal::None
+        let global = self.global(name, global);
+        self.globals.get(global, span)
     }
 
-    fn visit_literal(&mut self, literal: &Literal) -> Literal {
-        literal.clone()
+    fn visit_literal(&mut self, literal: &Literal) -> Result<Literal, RuntimeError> {
+        Ok(literal.clone())
     }
 
-    fn visit_assignment(&mut self, name: &String, value: &Box<Expression>) -> Literal {
-        let value = value.accept(self);
-        return self.environment.assign(name, value);
+    fn visit_assignment(
+        &mut self,
+        name: &str,
+        slot: &Cell<Option<Slot>>,
+        global: &Cell<Option<usize>>,
+        value: &Expression,
+        span: Span,
+    ) -> Result<Literal, RuntimeError> {
+        let value = value.accept(self)?;
+
+        match slot.get() {
+            Some(slot) => self.environment.assign(slot, value.clone()),
+            None => {
+                let global = self.global(name, global);
+                self.globals.assign(global, value.clone(), span)?;
+            }
+        }
+
+        Ok(value)
     }
 
-    fn visit_grouping(&mut self, expression: &Expression) -> Literal {
+    fn visit_grouping(&mut self, expression: &Expression) -> Result<Literal, RuntimeError> {
         expression.accept(self)
     }
+
+    fn visit_call(
+        &mut self,
+        callee: &Expression,
+        arguments: &[Expression],
+        span: Span,
+    ) -> Result<Literal, RuntimeError> {
+        let callee = callee.accept(self)?;
+
+        let arguments = arguments
+            .iter()
+            .map(|argument| argument.accept(self))
+            .collect::<Result<Vec<Literal>, RuntimeError>>()?;
+
+        match callee {
+            Literal::Function(function) => self.call(&function, arguments, span),
+            other => Err(RuntimeError::new(
+                format!("'{}' is not callable", other),
+                span,
+            )),
+        }
+    }
+
+    fn visit_list(&mut self, elements: &[Expression]) -> Result<Literal, RuntimeError> {
+        let elements = elements
+            .iter()
+            .map(|element| element.accept(self))
+            .collect::<Result<Vec<Literal>, RuntimeError>>()?;
+
+        Ok(Literal::List(Rc::new(RefCell::new(elements))))
+    }
+
+    fn visit_interpolation(&mut self, parts: &[Expression]) -> Result<Literal, RuntimeError> {
+        let mut value = String::new();
+
+        for part in parts {
+            value.push_str(&part.accept(self)?.to_string());
+        }
+
+        Ok(Literal::String(value))
+    }
+
+    fn visit_map(
+        &mut self,
+        entries: &[(Expression, Expression)],
+        span: Span,
+    ) -> Result<Literal, RuntimeError> {
+        let mut map = Map::new();
+
+        for (key, value) in entries {
+            let key = key.accept(self)?;
+            let value = value.accept(self)?;
+
+            if !Map::is_key(&key) {
+                return Err(RuntimeError::new(
+                    format!("A {} can't be used as a map key", key.type_name()),
+                    span,
+                ));
+            }
+            map.insert(key, value);
+        }
+
+        Ok(Literal::Map(Rc::new(RefCell::new(map))))
+    }
+
+    fn visit_index(
+        &mut self,
+        target: &Expression,
+        index: &Expression,
+        span: Span,
+    ) -> Result<Literal, RuntimeError> {
+        let target = target.accept(self)?;
+        let index = index.accept(self)?;
+
+        get_index(target, &index, span)
+    }
+
+    fn visit_index_assignment(
+        &mut self,
+        target: &Expression,
+        index: &Expression,
+        value: &Expression,
+        span: Span,
+    ) -> Result<Literal, RuntimeError> {
+        let target = target.accept(self)?;
+        let index = index.accept(self)?;
+        let value = value.accept(self)?;
+
+        set_index(target, index, value, span)
+    }
 }
 
-impl statement::Visitor<()> for Interpreter {
-    fn visit_program(&mut self, statements: &Vec<Statement>) -> () {
-        for statement in statements {
-            statement.accept(self);
+impl statement::Visitor<Result<Flow, RuntimeError>> for Interpreter {
+    fn visit_program(&mut self, statements: &[Statement]) -> Result<Flow, RuntimeError> {
+        self.execute_all(statements)
+    }
+
+    fn visit_block(&mut self, statements: &[Statement]) -> Result<Flow, RuntimeError> {
+        self.in_scope(|interpreter| interpreter.execute_all(statements))
+    }
+
+    fn visit_expression(&mut self, expression: &Expression) -> Result<Flow, RuntimeError> {
+        expression.accept(self)?;
+
+        Ok(Flow::Next)
+    }
+
+    fn visit_variable_declaration(
+        &mut self,
+        name: &str,
+        value: &Expression,
+        constant: bool,
+        span: Span,
+    ) -> Result<Flow, RuntimeError> {
+        let value = value.accept(self)?;
+        self.define(name, value, constant, span)?;
+
+        Ok(Flow::Next)
+    }
+
+    fn visit_while(&mut self, condition: &Expression, block: &Statement) -> Result<Flow, RuntimeError> {
+        self.execute_loop(Some(condition), None, block)
+    }
+
+    fn visit_for(
+        &mut self,
+        initializer: Option<&Statement>,
+        condition: Option<&Expression>,
+        increment: Option<&Expression>,
+        body: &Statement,
+    ) -> Result<Flow, RuntimeError> {
+        // The loop variable lives in its own scope around the whole loop.
+        self.in_scope(|interpreter| {
+            if let Some(initializer) = initializer {
+                initializer.accept(interpreter)?;
+            }
+
+            interpreter.execute_loop(condition, increment, body)
+        })
+    }
+
+    fn visit_for_in(
+        &mut self,
+        _name: &str,
+        iterable: &Expression,
+        body: &Statement,
+    ) -> Result<Flow, RuntimeError> {
+        let items = iterate(iterable.accept(self)?, iterable.span())?;
+
+        // Each item is a new variable, so closures made in the body capture
+        // that iteration's item.
+        for item in items {
+            let flow = self.in_scope(|interpreter| {
+                interpreter.environment.define(item);
+                body.accept(interpreter)
+            });
+
+            match flow? {
+                Flow::Break => break,
+                Flow::Next | Flow::Continue => {}
+                flow @ Flow::Return(_) => return Ok(flow),
+            }
         }
+
+        Ok(Flow::Next)
     }
 
-    fn visit_block(&mut self, statements: &Vec<Statement>) -> () {
-        let cloned_environment = self.environment.clone();
-        let _ = std::mem::replace(
-            &mut self.environment,
-            Environment::from_enclosing(cloned_environment),
-        );
+    fn visit_break(&mut self) -> Result<Flow, RuntimeError> {
+        Ok(Flow::Break)
+    }
 
-        for statement in statements {
-            statement.accept(self);
+    fn visit_continue(&mut self) -> Result<Flow, RuntimeError> {
+        Ok(Flow::Continue)
+    }
+
+    fn visit_if(
+        &mut self,
+        condition: &Expression,
+        then_branch: &Statement,
+        else_branch: Option<&Statement>,
+    ) -> Result<Flow, RuntimeError> {
+        if condition.accept(self)?.is_truthy() {
+            then_branch.accept(self)
+        } else if let Some(else_branch) = else_branch {
+            else_branch.accept(self)
+        } else {
+            Ok(Flow::Next)
+        }
+    }
+
+    fn visit_function(
+        &mut self,
+        name: &str,
+        parameters: &[String],
+        body: &Rc<Vec<Statement>>,
+        span: Span,
+    ) -> Result<Flow, RuntimeError> {
+        let function = |closure| {
+            Literal::Function(Rc::new(Function::new(
+                name.to_string(),
+                parameters.to_vec(),
+                Rc::clone(body),
+                closure,
+            )))
+        };
+
+        if self.scopes == 0 {
+            let closure = self.environment.capture();
+            self.define(name, function(closure), false, span)?;
+        } else {
+            // A local function is declared before it captures its scope, so
+            // that it can call itself.
+            let variable = self.environment.define(Literal::None);
+            let closure = self.environment.capture();
+            *variable.borrow_mut() = function(closure);
         }
 
-        self.environment = *self
-            .environment
-            .enclosing
-            .take()
-            .expect("Enclosing environment not found");
+        Ok(Flow::Next)
     }
 
-    fn visit_expression(&mut self, expression: &Expression) -> () {
-        expression.accept(self);
+    fn visit_return(&mut self, value: Option<&Expression>, _span: Span) -> Result<Flow, RuntimeError> {
+        let value = match value {
+            Some(value) => value.accept(self)?,
+            None => Literal::None,
+        };
+
+        Ok(Flow::Return(value))
     }
 
-    fn visit_variable_declaration(&mut self, name: &String, value: &Expression) -> () {
-        let value = value.accept(self);
-        self.environment.define(name.clone(), value);
+    fn visit_print(&mut self, expression: &Expression) -> Result<Flow, RuntimeError> {
+        println!("{}", expression.accept(self)?);
+
+        Ok(Flow::Next)
     }
+}
 
-    fn visit_while(&mut self, condition: &Expression, block: &Box<Statement>) -> () {
-        loop {
-            if let Literal::Boolean(value) = condition.accept(self) {
-                if !value {
-                    break;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mango::integer::Integer;
+    use crate::mango::parser::Parser;
+    use crate::mango::resolver::Resolver;
+    use crate::mango::scanner::Scanner;
+
+    /// Runs `source` to completion and returns the value of each of `names`,
+    /// which must be globals, as it would be printed.
+    fn run(source: &str, names: &[&str]) -> Vec<String> {
+        let tokens = Scanner::new(source.to_string()).scan().unwrap();
+        let (program, errors) = Parser::new(tokens).parse();
+        assert!(errors.is_empty(), "{errors:?}");
+        Resolver::new().resolve(&program).unwrap();
+
+        let mut interpreter = Interpreter::new();
+        interpreter.interpret(program).unwrap();
+
+        names
+            .iter()
+            .map(|name| interpreter.globals[*name].to_string())
+            .collect()
+    }
+
+    #[test]
+    fn while_body_mutates_outer_counters() {
+        let source = "
+            var count = 0;
+            while count < 5 {
+                count = count + 1;
+            }
+
+            fn sum(n) {
+                var total = 0;
+                var i = 1;
+                while i <= n {
+                    total = total + i;
+                    i = i + 1;
                 }
+                return total;
             }
+            var total = sum(10);
+        ";
 
-            block.accept(self);
-        }
+        assert_eq!(run(source, &["count", "total"]), ["5", "55"]);
     }
 
-    fn visit_print(&mut self, expression: &Expression) -> () {
-        println!("{}", expression.accept(self));
+    #[test]
+    fn shadowing_ends_with_the_block() {
+        let source = r#"
+            var name = "outer";
+            var seen = "";
+            {
+                var name = "inner";
+                name = name + "!";
+                seen = name;
+            }
+
+            fn shadow() {
+                var value = 1;
+                {
+                    var value = 2;
+                    value = 3;
+                }
+                return value;
+            }
+            var value = shadow();
+        "#;
+
+        assert_eq!(
+            run(source, &["name", "seen", "value"]),
+            ["outer", "inner!", "1"]
+        );
+    }
+
+    #[test]
+    fn nested_blocks_reach_outer_variables() {
+        let source = "
+            fn nested() {
+                var depth = 0;
+                var steps = [];
+                {
+                    var a = 1;
+                    {
+                        var b = 2;
+                        {
+                            depth = a + b;
+                            a = 10;
+                        }
+                    }
+                    steps = [a, depth];
+                }
+                return steps;
+            }
+            var steps = nested();
+
+            var outer = 0;
+            { { { outer = 3; } } }
+        ";
+
+        assert_eq!(run(source, &["steps", "outer"]), ["[10, 3]", "3"]);
+    }
+
+    #[test]
+    fn closures_keep_their_own_loop_variable() {
+        let source = "
+            var first;
+            var last;
+            for item in [1, 2, 3] {
+                fn get() {
+                    return item;
+                }
+                if item == 1 { first = get; }
+                last = get;
+            }
+            var values = [first(), last()];
+        ";
+
+        assert_eq!(run(source, &["values"]), ["[1, 3]"]);
+    }
+
+    #[test]
+    fn operators_group_as_the_precedence_table_says() {
+        let source = "
+            var difference = 10 - 3 - 2;
+            var quotient = 8 / 4 / 2;
+            var power = 2 ** 3 ** 2;
+            var mixed = 1 + 2 * 3 ** 2 ~/ 4 % 5;
+        ";
+
+        assert_eq!(
+            run(source, &["difference", "quotient", "power", "mixed"]),
+            ["5", "1.0", "512", "5"]
+        );
+    }
+
+    #[test]
+    fn reports_list_indices_too_big_for_an_i64_as_written() {
+        let message = |index: Literal| list_index(&index, 3, Span::default()).unwrap_err().message;
+        let big = Integer::parse("18446744073709551616", 10).unwrap();
+
+        assert_eq!(
+            message(Literal::Integer(big.clone())),
+            "Index 18446744073709551616 out of bounds for list of length 3"
+        );
+        assert_eq!(
+            message(Literal::Integer(big.neg())),
+            "Index -18446744073709551616 out of bounds for list of length 3"
+        );
+        assert_eq!(message(Literal::Integer(Integer::Small(-4))), "Index -4 out of bounds for list of length 3");
     }
 }
@@ -1,80 +1,412 @@
+use std::cell::RefCell;
+use std::cmp::Ordering;
 use std::fmt::{self, Display, Formatter};
-use std::ops::{Add, Div, Mul, Neg, Not, Sub};
+use std::ops::{Add, Div, Mul, Neg, Not, Rem, Sub};
+use std::rc::Rc;
 
-#[derive(Clone, Debug, PartialEq, PartialOrd)]
+use super::error::OperatorError;
+use super::function::Function;
+use super::integer::Integer;
+use super::map::Map;
+use super::vm::Closure;
+
+#[derive(Clone, Debug)]
 pub enum Literal {
+    Integer(Integer),
     Number(f64),
     Boolean(bool),
     String(String),
+    Function(Rc<Function>),
+    Closure(Rc<Closure>),
+    List(Rc<RefCell<Vec<Literal>>>),
+    Map(Rc<RefCell<Map>>),
 
     None,
 }
 
-impl Display for Literal {
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+impl Literal {
+    /// Whether the value counts as true wherever a condition is expected:
+    /// in `if` and `while`, for `!`, `and` and `or`. `false`, `none`, zero
+    /// and empty strings, lists and maps are false; everything else is true.
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Literal::Boolean(value) => *value,
+            Literal::None => false,
+            Literal::Integer(value) => !value.is_zero(),
+            Literal::Number(value) => *value != 0.0,
+            Literal::String(text) => !text.is_empty(),
+            Literal::List(elements) => !elements.borrow().is_empty(),
+            Literal::Map(map) => !map.borrow().is_empty(),
+            Literal::Function(_) | Literal::Closure(_) => true,
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
         match self {
-            Literal::Number(n) => write!(f, "{}", n),
+            Literal::Integer(_) => "integer",
+            Literal::Number(_) => "float",
+            Literal::Boolean(_) => "boolean",
+            Literal::String(_) => "string",
+            Literal::Function(_) | Literal::Closure(_) => "function",
+            Literal::List(_) => "list",
+            Literal::Map(_) => "map",
+            Literal::None => "none",
+        }
+    }
+
+    /// The value as a float, if it is a number of either kind.
+    pub fn as_float(&self) -> Option<f64> {
+        match self {
+            Literal::Integer(n) => Some(n.to_f64()),
+            Literal::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+}
+
+/// The lists and maps being printed further up the stack. One can contain
+/// itself, so walking into it again would never end.
+type Printing = Vec<*const ()>;
+
+/// The pairs of lists and maps being compared further up the stack.
+type Comparing = Vec<(*const (), *const ())>;
+
+impl Literal {
+    /// Formats a value inside a collection. Strings are quoted, so `["1"]` and
+    /// `[1]` print differently.
+    fn fmt_nested(&self, f: &mut Formatter<'_>, printing: &mut Printing) -> fmt::Result {
+        match self {
+            Literal::String(s) => write!(f, "{:?}", s),
+            other => other.fmt_with(f, printing),
+        }
+    }
+
+    /// `Display`, writing `[...]` or `{...}` for a list or map inside itself.
+    fn fmt_with(&self, f: &mut Formatter<'_>, printing: &mut Printing) -> fmt::Result {
+        match self {
+            Literal::Integer(n) => write!(f, "{}", n),
+            // Debug formatting keeps the `.0` on whole floats, so `1.0` and `1`
+            // print differently.
+            Literal::Number(n) => write!(f, "{:?}", n),
             Literal::Boolean(b) => write!(f, "{}", b),
             Literal::String(s) => write!(f, "{}", s),
-            Literal::None => write!(f, "None"),
+            Literal::Function(function) => write!(f, "<fn {}>", function.name),
+            Literal::Closure(closure) => write!(f, "<fn {}>", closure.prototype.name),
+            Literal::List(elements) => {
+                let pointer = Rc::as_ptr(elements) as *const ();
+                if printing.contains(&pointer) {
+                    return write!(f, "[...]");
+                }
+
+                printing.push(pointer);
+                write!(f, "[")?;
+                for (i, element) in elements.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    element.fmt_nested(f, printing)?;
+                }
+                printing.pop();
+                write!(f, "]")
+            }
+            Literal::Map(map) => {
+                let pointer = Rc::as_ptr(map) as *const ();
+                if printing.contains(&pointer) {
+                    return write!(f, "{{...}}");
+                }
+
+                printing.push(pointer);
+                write!(f, "{{")?;
+                for (i, (key, value)) in map.borrow().entries().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    key.fmt_nested(f, printing)?;
+                    write!(f, ": ")?;
+                    value.fmt_nested(f, printing)?;
+                }
+                printing.pop();
+                write!(f, "}}")
+            }
+            Literal::None => write!(f, "none"),
+        }
+    }
+
+    /// `==`, taking two lists or maps to be equal if the only difference
+    /// left to find is in a pair already being compared, as when each
+    /// contains itself.
+    fn equals(&self, other: &Self, comparing: &mut Comparing) -> bool {
+        match (self, other) {
+            (Literal::Integer(a), Literal::Integer(b)) => a == b,
+            (Literal::Boolean(a), Literal::Boolean(b)) => a == b,
+            (Literal::String(a), Literal::String(b)) => a == b,
+            (Literal::Function(a), Literal::Function(b)) => a == b,
+            (Literal::Closure(a), Literal::Closure(b)) => Rc::ptr_eq(a, b),
+            (Literal::List(a), Literal::List(b)) => {
+                let pair = (Rc::as_ptr(a) as *const (), Rc::as_ptr(b) as *const ());
+                if Rc::ptr_eq(a, b) || comparing.contains(&pair) {
+                    return true;
+                }
+
+                comparing.push(pair);
+                let (a, b) = (a.borrow(), b.borrow());
+                let equal = a.len() == b.len()
+                    && a.iter().zip(b.iter()).all(|(a, b)| a.equals(b, comparing));
+                comparing.pop();
+
+                equal
+            }
+            // Maps are equal when they hold the same entries, in any order.
+            (Literal::Map(a), Literal::Map(b)) => {
+                let pair = (Rc::as_ptr(a) as *const (), Rc::as_ptr(b) as *const ());
+                if Rc::ptr_eq(a, b) || comparing.contains(&pair) {
+                    return true;
+                }
+
+                comparing.push(pair);
+                let (a, b) = (a.borrow(), b.borrow());
+                let equal = a.len() == b.len()
+                    && a.entries().all(|(key, value)| {
+                        b.get(key).is_some_and(|other| value.equals(other, comparing))
+                    });
+                comparing.pop();
+
+                equal
+            }
+            (Literal::None, Literal::None) => true,
+            (a, b) => match (a.as_float(), b.as_float()) {
+                (Some(a), Some(b)) => a == b,
+                _ => false,
+            },
+        }
+    }
+
+    /// The ordering, comparing lists element by element. Like `equals`, it
+    /// doesn't walk into a pair of lists it is already comparing.
+    fn compare(&self, other: &Self, comparing: &mut Comparing) -> Option<Ordering> {
+        match (self, other) {
+            (Literal::Integer(a), Literal::Integer(b)) => a.partial_cmp(b),
+            (Literal::Boolean(a), Literal::Boolean(b)) => a.partial_cmp(b),
+            (Literal::String(a), Literal::String(b)) => a.partial_cmp(b),
+            (Literal::Function(a), Literal::Function(b)) => a.partial_cmp(b),
+            (Literal::Closure(a), Literal::Closure(b)) => {
+                Rc::ptr_eq(a, b).then_some(Ordering::Equal)
+            }
+            (Literal::List(a), Literal::List(b)) => {
+                let pair = (Rc::as_ptr(a) as *const (), Rc::as_ptr(b) as *const ());
+                if Rc::ptr_eq(a, b) || comparing.contains(&pair) {
+                    return Some(Ordering::Equal);
+                }
+
+                comparing.push(pair);
+                let (a, b) = (a.borrow(), b.borrow());
+                let mut ordering = a.len().partial_cmp(&b.len());
+                for (a, b) in a.iter().zip(b.iter()) {
+                    match a.compare(b, comparing) {
+                        Some(Ordering::Equal) => {}
+                        different => {
+                            ordering = different;
+                            break;
+                        }
+                    }
+                }
+                comparing.pop();
+
+                ordering
+            }
+            (Literal::Map(_), Literal::Map(_)) => {
+                self.equals(other, comparing).then_some(Ordering::Equal)
+            }
+            (Literal::None, Literal::None) => Some(Ordering::Equal),
+            (a, b) => a.as_float()?.partial_cmp(&b.as_float()?),
         }
     }
 }
 
+impl Literal {
+    /// Applies `<`, `<=`, `>` or `>=`, with `test` saying which orderings
+    /// make it true. Numbers of either kind order against each other, but
+    /// values of unrelated types can't be ordered at all, which is an error
+    /// rather than a quiet `false`.
+    pub fn order(
+        &self,
+        operator: &'static str,
+        other: &Literal,
+        test: impl Fn(Ordering) -> bool,
+    ) -> Result<Literal, OperatorError> {
+        let numbers = self.as_float().is_some() && other.as_float().is_some();
+        if !numbers && self.type_name() != other.type_name() {
+            return Err(unsupported(operator, self, other));
+        }
+
+        Ok(Literal::Boolean(self.partial_cmp(other).is_some_and(test)))
+    }
+}
+
+impl Display for Literal {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.fmt_with(f, &mut Vec::new())
+    }
+}
+
+// Integers and floats compare by value, so `1 == 1.0`. Values of unrelated
+// types are never equal and have no ordering.
+impl PartialEq for Literal {
+    fn eq(&self, other: &Self) -> bool {
+        self.equals(other, &mut Vec::new())
+    }
+}
+
+impl PartialOrd for Literal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.compare(other, &mut Vec::new())
+    }
+}
+
+/// Applies an arithmetic operator: exactly when both operands are integers,
+/// in floating point when either is a float. `None` from the integer
+/// operation means it divided by zero.
+fn arithmetic(
+    operator: &'static str,
+    left: Literal,
+    right: Literal,
+    integer: impl Fn(&Integer, &Integer) -> Option<Integer>,
+    float: impl Fn(f64, f64) -> f64,
+) -> Result<Literal, OperatorError> {
+    match (&left, &right) {
+        (Literal::Integer(a), Literal::Integer(b)) => integer(a, b)
+            .map(Literal::Integer)
+            .ok_or(OperatorError::DivisionByZero),
+        _ => match (left.as_float(), right.as_float()) {
+            (Some(a), Some(b)) => finite(operator, float(a, b), &left, &right),
+            _ => Err(unsupported(operator, &left, &right)),
+        },
+    }
+}
+
+/// Checks a float result. One too big for a float, or NaN, is an error like
+/// dividing by zero, unless an operand was already infinite or NaN and it is
+/// simply carried through.
+fn finite(
+    operator: &'static str,
+    result: f64,
+    left: &Literal,
+    right: &Literal,
+) -> Result<Literal, OperatorError> {
+    let carried = |operand: &Literal| matches!(operand, Literal::Number(n) if !n.is_finite());
+
+    if result.is_finite() || carried(left) || carried(right) {
+        Ok(Literal::Number(result))
+    } else {
+        Err(OperatorError::OutOfRange { operator })
+    }
+}
+
+fn unsupported(operator: &'static str, left: &Literal, right: &Literal) -> OperatorError {
+    OperatorError::Unsupported {
+        operator,
+        left: left.type_name(),
+        right: Some(right.type_name()),
+    }
+}
+
+/// Rejects a zero divisor before dividing, in integers or floats alike.
+fn check_divisor(divisor: &Literal) -> Result<(), OperatorError> {
+    if divisor.as_float() == Some(0.0) {
+        Err(OperatorError::DivisionByZero)
+    } else {
+        Ok(())
+    }
+}
+
 impl Add for Literal {
-    type Output = Self;
+    type Output = Result<Self, OperatorError>;
 
     fn add(self, other: Literal) -> Self::Output {
         match (self, other) {
-            (Literal::Number(a), Literal::Number(b)) => Literal::Number(a + b),
-            (Literal::String(a), Literal::String(b)) => Literal::String(format!("{a}{b}")),
-            (Literal::String(a), Literal::Number(b)) => Literal::String(format!("{a}{b}")),
-            (Literal::Number(a), Literal::String(b)) => Literal::String(format!("{a}{b}")),
-            _ => Literal::None,
+            (Literal::String(a), Literal::String(b)) => Ok(Literal::String(format!("{a}{b}"))),
+            (Literal::String(a), b) if b.as_float().is_some() => {
+                Ok(Literal::String(format!("{a}{b}")))
+            }
+            (a, Literal::String(b)) if a.as_float().is_some() => {
+                Ok(Literal::String(format!("{a}{b}")))
+            }
+            (a, b) => arithmetic("+", a, b, |a, b| Some(a.add(b)), |a, b| a + b),
         }
     }
 }
 
 impl Sub for Literal {
-    type Output = Self;
+    type Output = Result<Self, OperatorError>;
 
     fn sub(self, other: Literal) -> Self::Output {
-        match (self, other) {
-            (Literal::Number(a), Literal::Number(b)) => Literal::Number(a - b),
-            _ => Literal::None,
-        }
+        arithmetic("-", self, other, |a, b| Some(a.sub(b)), |a, b| a - b)
     }
 }
 
 impl Mul for Literal {
-    type Output = Self;
+    type Output = Result<Self, OperatorError>;
 
     fn mul(self, other: Literal) -> Self::Output {
-        match (self, other) {
-            (Literal::Number(a), Literal::Number(b)) => Literal::Number(a * b),
-            _ => Literal::None,
-        }
+        arithmetic("*", self, other, |a, b| Some(a.mul(b)), |a, b| a * b)
     }
 }
 
+// `/` is always true division, so `7 / 2` is `3.5`; `~/` divides integers.
 impl Div for Literal {
-    type Output = Self;
+    type Output = Result<Self, OperatorError>;
 
     fn div(self, other: Literal) -> Self::Output {
-        match (self, other) {
-            (Literal::Number(a), Literal::Number(b)) if b != 0.0 => Literal::Number(a / b),
-            _ => Literal::None,
+        match (self.as_float(), other.as_float()) {
+            (Some(_), Some(0.0)) => Err(OperatorError::DivisionByZero),
+            (Some(a), Some(b)) => finite("/", a / b, &self, &other),
+            _ => Err(unsupported("/", &self, &other)),
         }
     }
 }
 
+// The remainder takes the sign of the divisor, matching `~/`, so that
+// `a == (a ~/ b) * b + a % b`.
+impl Rem for Literal {
+    type Output = Result<Self, OperatorError>;
+
+    fn rem(self, other: Literal) -> Self::Output {
+        check_divisor(&other)?;
+        arithmetic("%", self, other, Integer::rem, floor_rem)
+    }
+}
+
+/// The float remainder matching `(a / b).floor()`.
+fn floor_rem(a: f64, b: f64) -> f64 {
+    a - b * (a / b).floor()
+}
+
+pub trait FloorDiv {
+    type Output;
+    fn floor_div(self, other: Literal) -> Self::Output;
+}
+
+impl FloorDiv for Literal {
+    type Output = Result<Self, OperatorError>;
+
+    fn floor_div(self, other: Literal) -> Self::Output {
+        check_divisor(&other)?;
+        arithmetic("~/", self, other, Integer::floor_div, |a, b| (a / b).floor())
+    }
+}
+
 impl Neg for Literal {
-    type Output = Self;
+    type Output = Result<Self, OperatorError>;
 
     fn neg(self) -> Self::Output {
         match self {
-            Literal::Number(a) => Literal::Number(-a),
-            _ => Literal::None,
+            Literal::Integer(a) => Ok(Literal::Integer(a.neg())),
+            Literal::Number(a) => Ok(Literal::Number(-a)),
+            other => Err(OperatorError::Unsupported {
+                operator: "-",
+                left: other.type_name(),
+                right: None,
+            }),
         }
     }
 }
@@ -83,10 +415,7 @@ impl Not for Literal {
     type Output = Self;
 
     fn not(self) -> Self::Output {
-        match self {
-            Literal::Boolean(a) => Literal::Boolean(!a),
-            _ => Literal::Boolean(false),
-        }
+        Literal::Boolean(!self.is_truthy())
     }
 }
 
@@ -96,12 +425,21 @@ pub trait Pow {
 }
 
 impl Pow for Literal {
-    type Output = Self;
+    type Output = Result<Self, OperatorError>;
 
     fn pow(self, exponent: Literal) -> Self::Output {
-        match (self, exponent) {
-            (Literal::Number(a), Literal::Number(b)) => Literal::Number(a.powf(b)),
-            _ => Literal::None,
+        match (&self, &exponent) {
+            // A negative power of an integer is a fraction, so only
+            // non-negative exponents stay exact.
+            (Literal::Integer(a), Literal::Integer(b)) if !b.is_negative() => a
+                .pow(b)
+                .map(Literal::Integer)
+                .ok_or(OperatorError::OutOfRange { operator: "**" }),
+            // Like division, `0 ** -1` is an error rather than `inf`.
+            _ => match (self.as_float(), exponent.as_float()) {
+                (Some(a), Some(b)) => finite("**", a.powf(b), &self, &exponent),
+                _ => Err(unsupported("**", &self, &exponent)),
+            },
         }
     }
 }
@@ -112,23 +450,157 @@ pub trait Fac {
 }
 
 impl Fac for Literal {
-    type Output = Self;
+    type Output = Result<Self, OperatorError>;
 
     fn fac(self) -> Self::Output {
-        match self {
-            Literal::Number(a) => {
-                let n = a as i64;
-                if n < 1 {
-                    return Literal::Number(0.0);
-                }
+        let out_of_range = OperatorError::OutOfRange { operator: "!" };
 
-                let mut result: i64 = 1;
-                for i in 1..=a as i64 {
-                    result *= i;
+        match self {
+            Literal::Integer(a) => a.factorial().map(Literal::Integer).ok_or(out_of_range),
+            Literal::Number(a) if a.fract() == 0.0 && a >= 0.0 && a <= i64::MAX as f64 => {
+                // From `171.0!` the result is too big for a float.
+                match Integer::Small(a as i64).factorial().map(|result| result.to_f64()) {
+                    Some(result) if result.is_finite() => Ok(Literal::Number(result)),
+                    _ => Err(out_of_range),
                 }
-                Literal::Number(result as f64)
             }
-            _ => Literal::None,
+            Literal::Number(_) => Err(out_of_range),
+            other => Err(OperatorError::Unsupported {
+                operator: "!",
+                left: other.type_name(),
+                right: None,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(elements: Vec<Literal>) -> Literal {
+        Literal::List(Rc::new(RefCell::new(elements)))
+    }
+
+    /// A list whose first element is the list itself, as after
+    /// `var l = [1, 2]; l[0] = l;`.
+    fn self_containing_list() -> Literal {
+        let l = list(vec![Literal::Integer(Integer::Small(1)), Literal::Integer(Integer::Small(2))]);
+        if let Literal::List(elements) = &l {
+            elements.borrow_mut()[0] = l.clone();
+        }
+        l
+    }
+
+    #[test]
+    fn prints_a_list_inside_itself_once() {
+        let l = self_containing_list();
+        assert_eq!(l.to_string(), "[[...], 2]");
+        assert_eq!(list(vec![l.clone(), l]).to_string(), "[[[...], 2], [[...], 2]]");
+    }
+
+    #[test]
+    fn compares_lists_inside_themselves() {
+        let (a, b) = (self_containing_list(), self_containing_list());
+        assert!(a == a.clone());
+        assert!(a == b);
+        assert_eq!(a.partial_cmp(&b), Some(Ordering::Equal));
+
+        let c = list(vec![a.clone(), Literal::Integer(Integer::Small(3))]);
+        assert!(a != c);
+    }
+
+    /// A map holding itself under `"self"`, as after
+    /// `var m = {"a": 1}; m["self"] = m;`.
+    fn self_containing_map() -> Literal {
+        let mut entries = Map::new();
+        entries.insert(Literal::String("a".to_string()), Literal::Integer(Integer::Small(1)));
+        let m = Literal::Map(Rc::new(RefCell::new(entries)));
+        if let Literal::Map(entries) = &m {
+            entries.borrow_mut().insert(Literal::String("self".to_string()), m.clone());
+        }
+        m
+    }
+
+    #[test]
+    fn prints_a_map_inside_itself_once() {
+        let m = self_containing_map();
+        assert_eq!(m.to_string(), r#"{"a": 1, "self": {...}}"#);
+        assert_eq!(list(vec![m.clone(), m]).to_string(), r#"[{"a": 1, "self": {...}}, {"a": 1, "self": {...}}]"#);
+    }
+
+    #[test]
+    fn compares_maps_inside_themselves() {
+        let (a, b) = (self_containing_map(), self_containing_map());
+        assert!(a == a.clone());
+        assert!(a == b);
+        assert_eq!(a.partial_cmp(&b), Some(Ordering::Equal));
+
+        if let Literal::Map(entries) = &b {
+            entries.borrow_mut().insert(Literal::String("a".to_string()), Literal::Integer(Integer::Small(2)));
+        }
+        assert!(a != b);
+        assert_eq!(a.partial_cmp(&b), None);
+    }
+
+    #[test]
+    fn refuses_float_arithmetic_too_big_for_a_float() {
+        let out_of_range = |operator| Err(OperatorError::OutOfRange { operator });
+        let (big, tiny) = (Literal::Number(1e308), Literal::Number(1e-308));
+        let ten = Literal::Integer(Integer::Small(10));
+
+        assert_eq!(big.clone() * ten.clone(), out_of_range("*"));
+        assert_eq!(big.clone() + big.clone(), out_of_range("+"));
+        assert_eq!(Literal::Number(-1e308) - big.clone(), out_of_range("-"));
+        assert_eq!(big.clone() / tiny, out_of_range("/"));
+        assert_eq!(big.clone() * Literal::Number(0.5), Ok(Literal::Number(5e307)));
+        assert_eq!(
+            Literal::Number(f64::INFINITY) * ten,
+            Ok(Literal::Number(f64::INFINITY))
+        );
+    }
+
+    #[test]
+    fn refuses_float_powers_and_factorials_too_big_for_a_float() {
+        let integer = |n| Literal::Integer(Integer::Small(n));
+        let out_of_range = |operator| Err(OperatorError::OutOfRange { operator });
+
+        assert_eq!(integer(0).pow(integer(-1)), out_of_range("**"));
+        assert_eq!(Literal::Number(10.0).pow(integer(400)), out_of_range("**"));
+        assert_eq!(integer(-8).pow(Literal::Number(0.5)), out_of_range("**"));
+        assert_eq!(integer(2).pow(integer(-1)), Ok(Literal::Number(0.5)));
+        assert_eq!(
+            Literal::Number(f64::INFINITY).pow(integer(2)),
+            Ok(Literal::Number(f64::INFINITY))
+        );
+
+        assert_eq!(Literal::Number(171.0).fac(), out_of_range("!"));
+        assert!(Literal::Number(170.0).fac().is_ok());
+    }
+
+    #[test]
+    fn orders_only_values_of_related_types() {
+        let integer = |n| Literal::Integer(Integer::Small(n));
+        let string = |s: &str| Literal::String(s.to_string());
+
+        assert_eq!(integer(1).order("<", &Literal::Number(1.5), Ordering::is_lt), Ok(Literal::Boolean(true)));
+        assert_eq!(string("b").order(">=", &string("a"), Ordering::is_ge), Ok(Literal::Boolean(true)));
+        assert_eq!(
+            Literal::Number(f64::NAN).order("<", &integer(1), Ordering::is_lt),
+            Ok(Literal::Boolean(false))
+        );
+
+        for (left, right) in [
+            (integer(1), string("a")),
+            (list(vec![integer(1)]), integer(2)),
+            (Literal::None, integer(1)),
+            (Literal::Boolean(true), integer(1)),
+        ] {
+            assert_eq!(
+                left.order("<", &right, Ordering::is_lt),
+                Err(unsupported("<", &left, &right))
+            );
+            assert!(left != right);
         }
     }
 }
@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+
+use super::integer::Integer;
+use super::literal::Literal;
+
+/// The hashable form of a map key. Only strings, numbers and booleans can be
+/// keys; numbers are compared by value, so `0`, `-0.0` and `0.0` are all the
+/// same key.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+enum Key {
+    String(String),
+    Integer(Integer),
+    Float(u64),
+    Boolean(bool),
+}
+
+impl Key {
+    fn of(literal: &Literal) -> Option<Key> {
+        match literal {
+            Literal::String(s) => Some(Key::String(s.clone())),
+            Literal::Integer(n) => Some(Key::Integer(n.clone())),
+            Literal::Number(n) if n.is_nan() => None,
+            // Whole floats share a key with the integer they equal. Past
+            // i64, that integer is spelled out from the float's exact value.
+            Literal::Number(n) if n.fract() == 0.0 && n.abs() < i64::MAX as f64 => {
+                Some(Key::Integer(Integer::Small(*n as i64)))
+            }
+            Literal::Number(n) if n.fract() == 0.0 => {
+                let magnitude = Integer::parse(&format!("{:.0}", n.abs()), 10)?;
+                let value = if *n < 0.0 { magnitude.neg() } else { magnitude };
+                Some(Key::Integer(value))
+            }
+            Literal::Number(n) => Some(Key::Float((n + 0.0).to_bits())),
+            Literal::Boolean(b) => Some(Key::Boolean(*b)),
+            _ => None,
+        }
+    }
+}
+
+/// A hash map that remembers insertion order, so iterating and printing a
+/// map always gives the same result.
+#[derive(Clone, Debug, Default)]
+pub struct Map {
+    entries: Vec<(Literal, Literal)>,
+    indices: HashMap<Key, usize>,
+}
+
+impl Map {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether `key` is of a type that can be used as a map key.
+    pub fn is_key(key: &Literal) -> bool {
+        Key::of(key).is_some()
+    }
+
+    pub fn get(&self, key: &Literal) -> Option<&Literal> {
+        let index = self.indices.get(&Key::of(key)?)?;
+        Some(&self.entries[*index].1)
+    }
+
+    /// Inserts or overwrites an entry. An overwritten key keeps its original
+    /// position. Returns `false`, leaving the map untouched, if `key` can't be
+    /// used as a key.
+    pub fn insert(&mut self, key: Literal, value: Literal) -> bool {
+        let Some(hashed) = Key::of(&key) else {
+            return false;
+        };
+
+        match self.indices.get(&hashed) {
+            Some(index) => self.entries[*index].1 = value,
+            None => {
+                self.indices.insert(hashed, self.entries.len());
+                self.entries.push((key, value));
+            }
+        }
+
+        true
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &Literal> {
+        self.entries.iter().map(|(key, _)| key)
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &(Literal, Literal)> {
+        self.entries.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn whole_floats_share_keys_with_equal_integers() {
+        let two = Integer::Small(2);
+        let cases = [
+            (Literal::Number(3.0), Integer::Small(3)),
+            (Literal::Number(-0.0), Integer::Small(0)),
+            (Literal::Number(2f64.powi(64)), two.pow(&Integer::Small(64)).unwrap()),
+            (Literal::Number(-(2f64.powi(200))), two.pow(&Integer::Small(200)).unwrap().neg()),
+        ];
+
+        for (float, integer) in cases {
+            let mut map = Map::new();
+            assert!(map.insert(Literal::Integer(integer.clone()), Literal::Boolean(true)));
+            assert_eq!(map.get(&float), Some(&Literal::Boolean(true)), "{float}");
+
+            assert!(map.insert(float, Literal::Boolean(false)));
+            assert_eq!(map.len(), 1);
+        }
+    }
+
+    #[test]
+    fn other_floats_are_keys_of_their_own() {
+        let mut map = Map::new();
+        assert!(map.insert(Literal::Number(0.5), Literal::None));
+        assert!(map.insert(Literal::Number(f64::INFINITY), Literal::None));
+        assert!(!map.insert(Literal::Number(f64::NAN), Literal::None));
+
+        assert_eq!(map.len(), 2);
+        assert!(map.get(&Literal::Integer(Integer::Small(0))).is_none());
+    }
+}
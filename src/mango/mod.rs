@@ -1,11 +1,20 @@
 pub mod expression;
 pub mod statement;
 
+pub mod compiler;
+pub mod diagnostic;
 pub mod environment;
+pub mod error;
+pub mod function;
 pub mod interpreter;
 pub mod parser;
+pub mod resolver;
 pub mod scanner;
+pub mod vm;
 
+pub mod integer;
 pub mod literal;
+pub mod map;
+pub mod span;
 pub mod token;
 pub mod token_type;
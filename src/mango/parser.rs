@@ -1,14 +1,94 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
 use crate::mango::literal::Literal;
 
+use super::error::ParseError;
 use super::expression::Expression;
+use super::span::Span;
 use super::statement::Statement;
 
 use super::token::Token;
 use super::token_type::TokenType;
 
+/// How tightly an operator binds, from loosest to tightest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Precedence {
+    Assignment,
+    Or,
+    And,
+    Comparison,
+    Term,
+    Factor,
+    Exponent,
+    Unary,
+}
+
+impl Precedence {
+    /// The next level up.
+    fn tighter(self) -> Self {
+        match self {
+            Precedence::Assignment => Precedence::Or,
+            Precedence::Or => Precedence::And,
+            Precedence::And => Precedence::Comparison,
+            Precedence::Comparison => Precedence::Term,
+            Precedence::Term => Precedence::Factor,
+            Precedence::Factor => Precedence::Exponent,
+            Precedence::Exponent | Precedence::Unary => Precedence::Unary,
+        }
+    }
+}
+
+/// Which way a chain of operators of the same level groups.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Associativity {
+    Left,
+    Right,
+}
+
+/// The infix operators, with how tightly each binds and which way it groups.
+/// Prefix operators and calls, indexes and factorials bind tighter than all
+/// of these.
+fn infix_rule(kind: &TokenType) -> Option<(Precedence, Associativity)> {
+    use Associativity::{Left, Right};
+
+    let rule = match kind {
+        TokenType::Equal => (Precedence::Assignment, Right),
+        TokenType::Or => (Precedence::Or, Left),
+        TokenType::And => (Precedence::And, Left),
+        TokenType::EqualEqual
+        | TokenType::BangEqual
+        | TokenType::Less
+        | TokenType::LessEqual
+        | TokenType::Greater
+        | TokenType::GreaterEqual => (Precedence::Comparison, Left),
+        TokenType::Plus | TokenType::Minus => (Precedence::Term, Left),
+        TokenType::Star | TokenType::Slash | TokenType::TildeSlash | TokenType::Percent => {
+            (Precedence::Factor, Left)
+        }
+        TokenType::StarStar => (Precedence::Exponent, Right),
+        _ => return None,
+    };
+
+    Some(rule)
+}
+
+/// How deeply expressions and statements can nest, counting brackets, prefix
+/// operators, right operands and blocks. Parsing recurses once per level, so
+/// without a limit a long enough run of `(` or `{` would overflow the stack.
+const MAX_NESTING: usize = 256;
+
+fn is_comparison(kind: &TokenType) -> bool {
+    matches!(infix_rule(kind), Some((Precedence::Comparison, _)))
+}
+
 pub struct Parser {
     tokens: Vec<Token>,
     position: usize,
+    errors: Vec<ParseError>,
+    function_depth: usize,
+    loop_depth: usize,
+    nesting: usize,
 }
 
 impl Parser {
@@ -16,270 +96,868 @@ impl Parser {
         Self {
             tokens,
             position: 0,
+            errors: Vec::new(),
+            function_depth: 0,
+            loop_depth: 0,
+            nesting: 0,
         }
     }
 
-    pub fn parse(&mut self) -> Statement {
+    /// Parses the whole token stream. Syntax errors don't stop the parser: each
+    /// one is recorded, the offending statement is skipped, and the program is
+    /// returned with every statement that did parse alongside all the errors.
+    pub fn parse(&mut self) -> (Statement, Vec<ParseError>) {
         let mut statements = Vec::<Statement>::new();
 
+        let start = self.peek(0).span;
+
         while !self.is_at_end() {
-            statements.push(self.statement());
+            if self.check(&TokenType::RightBrace) {
+                let token = self.advance();
+                self.errors.push(ParseError::new(
+                    "Unexpected '}' without a matching '{'".to_string(),
+                    token.span,
+                ));
+                continue;
+            }
+
+            if let Some(statement) = self.recovering_statement() {
+                statements.push(statement);
+            }
         }
 
-        Statement::Program { statements }
+        let program = Statement::Program {
+            statements,
+            span: start.to(self.peek(0).span),
+        };
+
+        (program, std::mem::take(&mut self.errors))
     }
 
-    fn statement(&mut self) -> Statement {
-        self.block()
+    /// Parses a statement, or records the error and skips ahead to the start of
+    /// the next one.
+    fn recovering_statement(&mut self) -> Option<Statement> {
+        let position = self.position;
+
+        match self.statement() {
+            Ok(statement) => Some(statement),
+            Err(error) => {
+                self.errors.push(error);
+
+                let opened = self.tokens[position..self.position]
+                    .iter()
+                    .map(|token| match token.kind {
+                        TokenType::LeftBrace => 1,
+                        TokenType::RightBrace => -1,
+                        _ => 0,
+                    })
+                    .sum::<isize>();
+                self.synchronize(opened.max(0) as usize);
+
+                // Always make progress, unless we stopped on the '}' that
+                // closes the block being parsed.
+                if self.position == position && !self.check(&TokenType::RightBrace) {
+                    self.advance();
+                }
+
+                None
+            }
+        }
     }
 
-    fn block(&mut self) -> Statement {
-        if self.expect(&[TokenType::LeftBrace]) {
-            let mut statements = Vec::<Statement>::new();
+    /// Discards tokens until a likely statement boundary: just past a ';', or
+    /// right before a '}' or a keyword that starts a statement. Braces are
+    /// skipped in pairs, `depth` of them already opened by the statement that
+    /// failed, so the block or map it failed in is left behind whole, along
+    /// with a ';' right after it.
+    fn synchronize(&mut self, mut depth: usize) {
+        while !self.is_at_end() {
+            if depth == 0 && self.position > 0 && self.previous().kind == TokenType::Semicolon {
+                return;
+            }
 
-            while self.peek(0).kind != TokenType::RightBrace {
-                statements.push(self.statement());
+            match self.peek(0).kind {
+                TokenType::LeftBrace => depth += 1,
+                TokenType::RightBrace if depth > 0 => {
+                    depth -= 1;
+                    if depth == 0 {
+                        self.advance();
+                        self.expect(&[TokenType::Semicolon]);
+                        return;
+                    }
+                }
+                TokenType::RightBrace
+                | TokenType::Var
+                | TokenType::Const
+                | TokenType::While
+                | TokenType::For
+                | TokenType::Break
+                | TokenType::Continue
+                | TokenType::If
+                | TokenType::Print
+                | TokenType::Fn
+                | TokenType::Return
+                    if depth == 0 =>
+                {
+                    return
+                }
+                _ => {}
             }
 
             self.advance();
+        }
+    }
+
+    fn statement(&mut self) -> Result<Statement, ParseError> {
+        self.nested(Self::block)
+    }
+
+    fn block(&mut self) -> Result<Statement, ParseError> {
+        if self.check(&TokenType::LeftBrace) && !self.starts_map() {
+            self.advance();
+            let (statements, span) = self.block_body()?;
 
-            return Statement::Block { statements };
+            return Ok(Statement::Block { statements, span });
         }
 
         self.while_statement()
     }
 
-    fn while_statement(&mut self) -> Statement {
+    /// Whether the '{' at the cursor opens a map literal rather than a block,
+    /// judging by whether it is followed by a simple key and a ':'. An empty
+    /// `{}` is a block.
+    fn starts_map(&self) -> bool {
+        matches!(
+            self.peek(1).kind,
+            TokenType::String
+                | TokenType::Number
+                | TokenType::True
+                | TokenType::False
+                | TokenType::Identifier
+        ) && self.peek(2).kind == TokenType::Colon
+    }
+
+    /// Parses the statements of a block whose '{' was just consumed, returning
+    /// them with the span of the whole block.
+    fn block_body(&mut self) -> Result<(Vec<Statement>, Span), ParseError> {
+        let start = self.previous().span;
+        let mut statements = Vec::<Statement>::new();
+
+        while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
+            if let Some(statement) = self.recovering_statement() {
+                statements.push(statement);
+            }
+        }
+
+        let end = self.consume(
+            TokenType::RightBrace,
+            "'}' Expected after block".to_string(),
+        )?;
+
+        Ok((statements, start.to(end.span)))
+    }
+
+    fn while_statement(&mut self) -> Result<Statement, ParseError> {
         if self.expect(&[TokenType::While]) {
-            let condition = self.expression();
-            let block = self.block();
+            let start = self.previous().span;
+            let condition = self.expression()?;
+            let block = self.loop_body()?;
 
-            return Statement::While {
+            return Ok(Statement::While {
                 condition,
+                span: start.to(block.span()),
                 block: Box::new(block),
+            });
+        }
+
+        self.for_statement()
+    }
+
+    fn for_statement(&mut self) -> Result<Statement, ParseError> {
+        if self.expect(&[TokenType::For]) {
+            let start = self.previous().span;
+
+            if self.check(&TokenType::Identifier) && self.peek(1).kind == TokenType::In {
+                let (name, _) = self.identifier("'Identifier' Expected after 'for'".to_string())?;
+                self.advance();
+
+                let iterable = self.expression()?;
+                let body = self.loop_body()?;
+
+                return Ok(Statement::ForIn {
+                    name,
+                    iterable,
+                    span: start.to(body.span()),
+                    body: Box::new(body),
+                });
+            }
+
+            let initializer = if self.expect(&[TokenType::Semicolon]) {
+                None
+            } else if self.check(&TokenType::Var) {
+                Some(self.variable_declaration()?)
+            } else {
+                Some(self.expression_statement()?)
+            };
+
+            let condition = if self.check(&TokenType::Semicolon) {
+                None
+            } else {
+                Some(self.expression()?)
+            };
+            self.consume(
+                TokenType::Semicolon,
+                "';' Expected after loop condition".to_string(),
+            )?;
+
+            let increment = if self.check(&TokenType::LeftBrace) {
+                None
+            } else {
+                Some(self.expression()?)
+            };
+
+            let body = self.loop_body()?;
+
+            return Ok(Statement::For {
+                initializer: initializer.map(Box::new),
+                condition,
+                increment,
+                span: start.to(body.span()),
+                body: Box::new(body),
+            });
+        }
+
+        self.if_statement()
+    }
+
+    /// Parses the body of an `if` or a loop. A lone statement is wrapped in a
+    /// block, so a declaration in it gets a scope of its own.
+    fn body(&mut self) -> Result<Statement, ParseError> {
+        let statement = self.block()?;
+
+        match statement {
+            Statement::Block { .. } => Ok(statement),
+            statement => Ok(Statement::Block {
+                span: statement.span(),
+                statements: vec![statement],
+            }),
+        }
+    }
+
+    fn loop_body(&mut self) -> Result<Statement, ParseError> {
+        self.loop_depth += 1;
+        let body = self.body();
+        self.loop_depth -= 1;
+
+        body
+    }
+
+    fn if_statement(&mut self) -> Result<Statement, ParseError> {
+        if self.expect(&[TokenType::If]) {
+            let start = self.previous().span;
+            let condition = self.expression()?;
+            let then_branch = self.body()?;
+
+            let else_branch = if self.expect(&[TokenType::Else]) {
+                if self.check(&TokenType::If) {
+                    Some(self.nested(Self::if_statement)?)
+                } else {
+                    Some(self.body()?)
+                }
+            } else {
+                None
             };
+
+            let end = else_branch.as_ref().unwrap_or(&then_branch).span();
+
+            return Ok(Statement::If {
+                condition,
+                then_branch: Box::new(then_branch),
+                else_branch: else_branch.map(Box::new),
+                span: start.to(end),
+            });
         }
 
         self.print()
     }
 
-    fn print(&mut self) -> Statement {
+    fn print(&mut self) -> Result<Statement, ParseError> {
         if self.expect(&[TokenType::Print]) {
-            let expression = self.expression();
-            let statement = Statement::Print { expression };
+            let start = self.previous().span;
+            let expression = self.expression()?;
 
-            self.consume(
+            let end = self.consume(
                 TokenType::Semicolon,
                 "';' Expected after statement".to_string(),
-            );
+            )?;
 
-            return statement;
+            return Ok(Statement::Print {
+                expression,
+                span: start.to(end.span),
+            });
         }
 
         self.variable_declaration()
     }
 
-    fn variable_declaration(&mut self) -> Statement {
-        if self.expect(&[TokenType::Var]) {
-            let identifier = self.consume(
-                TokenType::Identifier,
-                "'Identifier' Expected after 'var'".to_string(),
-            );
-            let name: String;
-
-            if let Literal::String(var_name) = identifier.literal {
-                name = var_name;
+    fn variable_declaration(&mut self) -> Result<Statement, ParseError> {
+        if self.expect(&[TokenType::Var, TokenType::Const]) {
+            let Token {
+                kind,
+                span: start,
+                doc,
+                ..
+            } = self.previous();
+            let constant = kind == TokenType::Const;
+            let keyword = if constant { "const" } else { "var" };
+            let (name, name_span) =
+                self.identifier(format!("'Identifier' Expected after '{keyword}'"))?;
+
+            // A variable declared without a value starts out as `none`.
+            let value = if !constant && self.check(&TokenType::Semicolon) {
+                Expression::Literal {
+                    value: Literal::None,
+                    span: name_span,
+                }
             } else {
-                panic!("Variable name must be alphanumerical");
-            }
-            self.consume(
-                TokenType::Equal,
-                "'=' Expected after variable declaration name".to_string(),
-            );
-
-            let value = self.expression();
+                self.consume(
+                    TokenType::Equal,
+                    "'=' Expected after variable declaration name".to_string(),
+                )
+                .map_err(|error| {
+                    if constant {
+                        error.with_help("a constant must be given its value when declared".to_string())
+                    } else {
+                        error
+                    }
+                })?;
+
+                self.expression()?
+            };
 
-            self.consume(
+            let end = self.consume(
                 TokenType::Semicolon,
                 "';' Expected after statement".to_string(),
-            );
-
-            return Statement::VariableDeclaration { name, value };
+            )?;
+
+            return Ok(Statement::VariableDeclaration {
+                name,
+                value,
+                constant,
+                doc,
+                span: start.to(end.span),
+            });
         }
 
-        self.expression_statement()
+        self.function_declaration()
     }
 
-    fn expression_statement(&mut self) -> Statement {
-        let expression = self.expression();
+    fn function_declaration(&mut self) -> Result<Statement, ParseError> {
+        if self.expect(&[TokenType::Fn]) {
+            let Token { span: start, doc, .. } = self.previous();
+            let (name, _) = self.identifier("'Identifier' Expected after 'fn'".to_string())?;
 
-        self.consume(
-            TokenType::Semicolon,
-            "';' Expected after statement".to_string(),
-        );
+            self.consume(
+                TokenType::LeftParen,
+                "'(' Expected after function name".to_string(),
+            )?;
+
+            let mut parameters = Vec::<String>::new();
+            if !self.check(&TokenType::RightParen) {
+                loop {
+                    let (parameter, span) =
+                        self.identifier("'Identifier' Expected as parameter name".to_string())?;
+
+                    if parameters.contains(&parameter) {
+                        return Err(ParseError::new(
+                            format!("Duplicate parameter '{parameter}'"),
+                            span,
+                        ));
+                    }
+                    parameters.push(parameter);
+
+                    if !self.expect(&[TokenType::Comma]) {
+                        break;
+                    }
+                }
+            }
 
-        Statement::Expression {
-            expression: expression,
+            self.consume(
+                TokenType::RightParen,
+                "')' Expected after parameters".to_string(),
+            )?;
+            self.consume(
+                TokenType::LeftBrace,
+                "'{' Expected before function body".to_string(),
+            )?;
+
+            // A function body starts outside of any loop, even when the
+            // function itself is declared inside one.
+            let loop_depth = std::mem::take(&mut self.loop_depth);
+            self.function_depth += 1;
+            let body = self.block_body();
+            self.function_depth -= 1;
+            self.loop_depth = loop_depth;
+            let (body, end) = body?;
+
+            return Ok(Statement::Function {
+                name,
+                parameters,
+                body: Rc::new(body),
+                doc,
+                span: start.to(end),
+            });
         }
-    }
 
-    fn expression(&mut self) -> Expression {
-        self.assignment()
+        self.return_statement()
     }
 
-    fn assignment(&mut self) -> Expression {
-        let mut expression = self.equality();
+    fn return_statement(&mut self) -> Result<Statement, ParseError> {
+        if self.expect(&[TokenType::Return]) {
+            let start = self.previous().span;
 
-        if self.expect(&[TokenType::Equal]) {
-            let right = self.assignment();
+            if self.function_depth == 0 {
+                return Err(ParseError::new(
+                    "'return' outside of a function".to_string(),
+                    start,
+                ));
+            }
 
-            if let Expression::Variable(name) = expression {
-                expression = Expression::Assignment {
-                    name,
-                    value: Box::new(right),
-                };
+            let value = if self.check(&TokenType::Semicolon) {
+                None
             } else {
-                panic!("Assignment type must be an identifier.")
-            }
+                Some(self.expression()?)
+            };
+
+            let end = self.consume(
+                TokenType::Semicolon,
+                "';' Expected after statement".to_string(),
+            )?;
+
+            return Ok(Statement::Return {
+                value,
+                span: start.to(end.span),
+            });
         }
 
-        expression
+        self.loop_control()
     }
 
-    fn equality(&mut self) -> Expression {
-        let mut expression = self.term();
+    fn loop_control(&mut self) -> Result<Statement, ParseError> {
+        if self.expect(&[TokenType::Break, TokenType::Continue]) {
+            let keyword = self.previous();
 
-        if self.expect(&[
-            TokenType::EqualEqual,
-            TokenType::Greater,
-            TokenType::GreaterEqual,
-            TokenType::Less,
-            TokenType::LessEqual,
-        ]) {
-            let operator = self.previous();
-            let right = self.term();
-
-            expression = Expression::Binary {
-                left: Box::new(expression),
-                operator,
-                right: Box::new(right),
+            if self.loop_depth == 0 {
+                return Err(ParseError::new(
+                    format!("'{}' outside of a loop", keyword.literal),
+                    keyword.span,
+                ));
             }
+
+            let end = self.consume(
+                TokenType::Semicolon,
+                "';' Expected after statement".to_string(),
+            )?;
+            let span = keyword.span.to(end.span);
+
+            return Ok(match keyword.kind {
+                TokenType::Break => Statement::Break { span },
+                _ => Statement::Continue { span },
+            });
         }
 
-        expression
+        self.expression_statement()
     }
 
-    fn term(&mut self) -> Expression {
-        let mut expression = self.factor();
+    fn expression_statement(&mut self) -> Result<Statement, ParseError> {
+        let expression = self.expression()?;
 
-        if self.expect(&[TokenType::Plus, TokenType::Minus]) {
-            let operator = self.previous();
-            let right = self.term();
-
-            expression = Expression::Binary {
-                left: Box::new(expression),
-                operator,
-                right: Box::new(right),
-            }
-        }
+        let end = self.consume(
+            TokenType::Semicolon,
+            "';' Expected after statement".to_string(),
+        )?;
 
-        expression
+        Ok(Statement::Expression {
+            span: expression.span().to(end.span),
+            expression,
+        })
     }
 
-    fn factor(&mut self) -> Expression {
-        let mut expression = self.exponent();
+    fn expression(&mut self) -> Result<Expression, ParseError> {
+        self.parse_precedence(Precedence::Assignment)
+    }
 
-        if self.expect(&[TokenType::Star, TokenType::Slash]) {
-            let operator = self.previous();
-            let right = self.factor();
+    /// Parses an expression made of operators that bind at least as tightly
+    /// as `minimum`, leaving any looser one for the caller.
+    fn parse_precedence(&mut self, minimum: Precedence) -> Result<Expression, ParseError> {
+        self.nested(|parser| parser.operators(minimum))
+    }
 
-            expression = Expression::Binary {
-                left: Box::new(expression),
-                operator,
-                right: Box::new(right),
-            }
+    /// Runs `parse` one level further in, failing past `MAX_NESTING`.
+    fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<T, ParseError> {
+        if self.nesting == MAX_NESTING {
+            return Err(ParseError::new(
+                "Nested too deeply".to_string(),
+                self.peek(0).span,
+            ));
         }
 
-        expression
+        self.nesting += 1;
+        let result = parse(self);
+        self.nesting -= 1;
+
+        result
     }
 
-    fn exponent(&mut self) -> Expression {
-        let mut expression = self.unary();
+    fn operators(&mut self, minimum: Precedence) -> Result<Expression, ParseError> {
+        let mut expression = self.prefix()?;
 
-        if self.expect(&[TokenType::StarStar]) {
-            let operator = self.previous();
-            let right = self.exponent();
+        while let Some((precedence, associativity)) = infix_rule(&self.peek(0).kind) {
+            if precedence < minimum {
+                break;
+            }
 
-            expression = Expression::Binary {
-                left: Box::new(expression),
-                operator: operator,
-                right: Box::new(right),
+            let operator = self.advance();
+
+            // A left-associative operator leaves an operator of its own level
+            // on the right for this loop, so `a - b - c` is `(a - b) - c`.
+            let right_minimum = match associativity {
+                Associativity::Left => precedence.tighter(),
+                Associativity::Right => precedence,
             };
+            let right = self.parse_precedence(right_minimum)?;
+
+            expression = self.infix(expression, operator, right)?;
         }
 
-        expression
+        Ok(expression)
     }
 
-    fn unary(&mut self) -> Expression {
+    /// Builds the node for an infix operator applied to its operands.
+    fn infix(
+        &self,
+        left: Expression,
+        operator: Token,
+        right: Expression,
+    ) -> Result<Expression, ParseError> {
+        let span = left.span().to(right.span());
+
+        let expression = match operator.kind {
+            TokenType::Equal => match left {
+                Expression::Variable { name, .. } => Expression::Assignment {
+                    name,
+                    slot: Cell::new(None),
+                    global: Cell::new(None),
+                    value: Box::new(right),
+                    span,
+                },
+                Expression::Index { target, index, .. } => Expression::IndexAssignment {
+                    target,
+                    index,
+                    value: Box::new(right),
+                    span,
+                },
+                _ => {
+                    return Err(ParseError::new(
+                        "Assignment target must be an identifier or an index".to_string(),
+                        left.span(),
+                    ))
+                }
+            },
+            // A comparison on top of another one extends the chain, rather
+            // than comparing against its result.
+            _ if is_comparison(&operator.kind) => match left {
+                Expression::Binary {
+                    left: first,
+                    operator: first_operator,
+                    right: second,
+                    ..
+                } if is_comparison(&first_operator.kind) => Expression::Comparison {
+                    operands: vec![*first, *second, right],
+                    operators: vec![first_operator, operator],
+                    span,
+                },
+                Expression::Comparison {
+                    mut operands,
+                    mut operators,
+                    ..
+                } => {
+                    operands.push(right);
+                    operators.push(operator);
+                    Expression::Comparison {
+                        operands,
+                        operators,
+                        span,
+                    }
+                }
+                left => Expression::Binary {
+                    left: Box::new(left),
+                    operator,
+                    right: Box::new(right),
+                    span,
+                },
+            },
+            TokenType::And | TokenType::Or => Expression::Logical {
+                left: Box::new(left),
+                operator,
+                right: Box::new(right),
+                span,
+            },
+            _ => Expression::Binary {
+                left: Box::new(left),
+                operator,
+                right: Box::new(right),
+                span,
+            },
+        };
+
+        Ok(expression)
+    }
+
+    /// Parses a prefix operator and its operand, or an operand on its own.
+    fn prefix(&mut self) -> Result<Expression, ParseError> {
         if self.expect(&[TokenType::Bang, TokenType::Minus]) {
             let operator = self.previous();
-            let right = self.unary();
+            let right = self.parse_precedence(Precedence::Unary)?;
 
-            return Expression::Unary {
+            return Ok(Expression::Unary {
+                span: operator.span.to(right.span()),
                 operator,
                 right: Box::new(right),
                 is_prefix: true,
-            };
+            });
         }
 
-        let mut expression = self.primary();
+        self.call()
+    }
 
-        if self.expect(&[TokenType::Bang]) {
-            let operator = self.previous();
+    /// Parses an operand followed by any calls, indexes and factorials, which
+    /// bind tighter than every other operator.
+    fn call(&mut self) -> Result<Expression, ParseError> {
+        let mut expression = self.primary()?;
+
+        loop {
+            if self.expect(&[TokenType::LeftParen]) {
+                let (arguments, end) = self.expression_list(
+                    TokenType::RightParen,
+                    "')' Expected after arguments".to_string(),
+                )?;
+
+                expression = Expression::Call {
+                    span: expression.span().to(end.span),
+                    callee: Box::new(expression),
+                    arguments,
+                };
+            } else if self.expect(&[TokenType::LeftBracket]) {
+                let index = self.expression()?;
+                let end = self.consume(
+                    TokenType::RightBracket,
+                    "']' Expected after index".to_string(),
+                )?;
+
+                expression = Expression::Index {
+                    span: expression.span().to(end.span),
+                    target: Box::new(expression),
+                    index: Box::new(index),
+                };
+            } else if self.expect(&[TokenType::Bang]) {
+                let operator = self.previous();
+
+                expression = Expression::Unary {
+                    span: expression.span().to(operator.span),
+                    operator,
+                    right: Box::new(expression),
+                    is_prefix: false,
+                };
+            } else {
+                break;
+            }
+        }
 
-            expression = Expression::Unary {
-                operator: operator,
-                right: Box::new(expression),
-                is_prefix: false,
-            };
+        Ok(expression)
+    }
+
+    /// Parses an interpolated string whose first `StringPart` was just
+    /// consumed, into its text and embedded expressions in order.
+    fn interpolation(&mut self) -> Result<Expression, ParseError> {
+        let first = self.previous();
+        let start = first.span;
+        let mut parts = vec![Expression::Literal {
+            value: first.literal,
+            span: first.span,
+        }];
+
+        loop {
+            parts.push(self.expression()?);
+
+            if self.expect(&[TokenType::StringPart]) {
+                let part = self.previous();
+                parts.push(Expression::Literal {
+                    value: part.literal,
+                    span: part.span,
+                });
+                continue;
+            }
+
+            let end = self.consume(
+                TokenType::String,
+                "'}' Expected to close interpolation".to_string(),
+            )?;
+            parts.push(Expression::Literal {
+                value: end.literal,
+                span: end.span,
+            });
+
+            return Ok(Expression::Interpolation {
+                parts,
+                span: start.to(end.span),
+            });
         }
+    }
+
+    /// Parses comma separated expressions up to and including `closing`,
+    /// allowing a trailing comma.
+    fn expression_list(
+        &mut self,
+        closing: TokenType,
+        message: String,
+    ) -> Result<(Vec<Expression>, Token), ParseError> {
+        let mut expressions = Vec::<Expression>::new();
 
-        expression
+        while !self.check(&closing) {
+            expressions.push(self.expression()?);
+
+            if !self.expect(&[TokenType::Comma]) {
+                break;
+            }
+        }
+
+        let end = self.consume(closing, message)?;
+
+        Ok((expressions, end))
     }
 
-    fn primary(&mut self) -> Expression {
+    fn primary(&mut self) -> Result<Expression, ParseError> {
         if self.expect(&[TokenType::True]) {
-            return Expression::Literal(Literal::Boolean(true));
+            return Ok(Expression::Literal {
+                value: Literal::Boolean(true),
+                span: self.previous().span,
+            });
         }
         if self.expect(&[TokenType::False]) {
-            return Expression::Literal(Literal::Boolean(false));
+            return Ok(Expression::Literal {
+                value: Literal::Boolean(false),
+                span: self.previous().span,
+            });
+        }
+        if self.expect(&[TokenType::None]) {
+            return Ok(Expression::Literal {
+                value: Literal::None,
+                span: self.previous().span,
+            });
         }
-        if self.expect(&[TokenType::Number]) {
-            return Expression::Literal(self.previous().literal);
+        if self.expect(&[TokenType::Number, TokenType::String]) {
+            let token = self.previous();
+            return Ok(Expression::Literal {
+                value: token.literal,
+                span: token.span,
+            });
         }
-        if self.expect(&[TokenType::String]) {
-            return Expression::Literal(self.previous().literal);
+        if self.expect(&[TokenType::StringPart]) {
+            return self.interpolation();
         }
         if self.expect(&[TokenType::Identifier]) {
-            if let Literal::String(name) = self.previous().literal {
-                return Expression::Variable(name);
+            let token = self.previous();
+            if let Literal::String(name) = token.literal {
+                return Ok(Expression::Variable {
+                    name,
+                    slot: Cell::new(None),
+                    global: Cell::new(None),
+                    span: token.span,
+                });
             } else {
-                return Expression::Literal(Literal::None);
+                return Ok(Expression::Literal {
+                    value: Literal::None,
+                    span: token.span,
+                });
             }
         }
 
+        if self.expect(&[TokenType::LeftBrace]) {
+            let start = self.previous().span;
+            let mut entries = Vec::<(Expression, Expression)>::new();
+
+            while !self.check(&TokenType::RightBrace) {
+                let key = self.expression()?;
+                self.consume(
+                    TokenType::Colon,
+                    "':' Expected after map key".to_string(),
+                )?;
+                let value = self.expression()?;
+                entries.push((key, value));
+
+                if !self.expect(&[TokenType::Comma]) {
+                    break;
+                }
+            }
+
+            let end = self.consume(
+                TokenType::RightBrace,
+                "'}' Expected after map entries".to_string(),
+            )?;
+
+            return Ok(Expression::Map {
+                entries,
+                span: start.to(end.span),
+            });
+        }
+
+        if self.expect(&[TokenType::LeftBracket]) {
+            let start = self.previous().span;
+            let (elements, end) = self.expression_list(
+                TokenType::RightBracket,
+                "']' Expected after list elements".to_string(),
+            )?;
+
+            return Ok(Expression::List {
+                elements,
+                span: start.to(end.span),
+            });
+        }
+
         if self.expect(&[TokenType::LeftParen]) {
-            let expression = Expression::Grouping {
-                expression: Box::new(self.expression()),
-            };
-            self.consume(
+            let start = self.previous().span;
+            let expression = self.expression()?;
+            let end = self.consume(
                 TokenType::RightParen,
                 "')' Expected closing parenthesis".to_string(),
-            );
-            return expression;
+            )?;
+            return Ok(Expression::Grouping {
+                expression: Box::new(expression),
+                span: start.to(end.span),
+            });
         }
 
-        panic!("Uhh ohh {:?}", self.peek(0));
+        let token = self.peek(0);
+        Err(ParseError::new(
+            format!("Expected expression, found {:?}", token.kind),
+            token.span,
+        ))
+    }
+
+    fn identifier(&mut self, message: String) -> Result<(String, Span), ParseError> {
+        let identifier = self.consume(TokenType::Identifier, message)?;
+
+        match identifier.literal {
+            Literal::String(name) => Ok((name, identifier.span)),
+            _ => Err(ParseError::new(
+                "Identifier must be alphanumerical".to_string(),
+                identifier.span,
+            )),
+        }
     }
 
     fn previous(&self) -> Token {
@@ -302,7 +980,7 @@ impl Parser {
     }
 
     fn peek(&self, offset: usize) -> Token {
-        if self.position + offset > self.tokens.len() {
+        if self.position + offset >= self.tokens.len() {
             return self.tokens.last().unwrap().clone();
         }
         self.tokens[self.position + offset].clone()
@@ -316,18 +994,335 @@ impl Parser {
         let token = self.tokens[self.position].clone();
         self.position += 1;
 
-        return token;
+        token
     }
 
-    fn consume(&mut self, kind: TokenType, message: String) -> Token {
+    fn consume(&mut self, kind: TokenType, message: String) -> Result<Token, ParseError> {
         if self.check(&kind) {
-            return self.advance();
+            return Ok(self.advance());
+        }
+
+        // A missing ';' belongs at the end of the statement, not at whatever
+        // token happens to follow it, which is often on the next line.
+        if kind == TokenType::Semicolon && self.position > 0 {
+            let span = self.previous().span.shrink_to_end();
+
+            return Err(ParseError::new(message, span)
+                .with_help("did you forget ';'?".to_string()));
         }
 
-        panic!("{message}");
+        Err(ParseError::new(message, self.peek(0).span))
     }
 
     fn is_at_end(&self) -> bool {
         self.peek(0).kind == TokenType::End
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mango::environment::Slot;
+    use crate::mango::expression::Visitor;
+    use crate::mango::scanner::Scanner;
+
+    /// Writes an expression as an S-expression, so a test can see how it was
+    /// grouped. Operators are taken from the source text.
+    struct Shape<'a> {
+        source: &'a str,
+    }
+
+    impl<'a> Shape<'a> {
+        fn text(&self, token: &Token) -> &'a str {
+            &self.source[token.span.start..token.span.end]
+        }
+
+        fn all(&mut self, expressions: &[Expression]) -> String {
+            let parts: Vec<String> = expressions.iter().map(|e| e.accept(self)).collect();
+            parts.join(" ")
+        }
+    }
+
+    impl Visitor<String> for Shape<'_> {
+        fn visit_binary(&mut self, left: &Expression, operator: &Token, right: &Expression) -> String {
+            format!("({} {} {})", self.text(operator), left.accept(self), right.accept(self))
+        }
+
+        fn visit_logical(&mut self, left: &Expression, operator: &Token, right: &Expression) -> String {
+            self.visit_binary(left, operator, right)
+        }
+
+        fn visit_comparison(&mut self, operands: &[Expression], operators: &[Token]) -> String {
+            let mut chain = operands[0].accept(self);
+            for (operator, operand) in operators.iter().zip(&operands[1..]) {
+                chain = format!("{} {} {}", chain, self.text(operator), operand.accept(self));
+            }
+            format!("(chain {})", chain)
+        }
+
+        fn visit_unary(&mut self, operator: &Token, right: &Expression, is_prefix: bool) -> String {
+            if is_prefix {
+                format!("({} {})", self.text(operator), right.accept(self))
+            } else {
+                format!("({} {})", right.accept(self), self.text(operator))
+            }
+        }
+
+        fn visit_literal(&mut self, literal: &Literal) -> String {
+            literal.to_string()
+        }
+
+        fn visit_variable(
+            &mut self,
+            name: &str,
+            _slot: &Cell<Option<Slot>>,
+            _global: &Cell<Option<usize>>,
+            _span: Span,
+        ) -> String {
+            name.to_string()
+        }
+
+        fn visit_assignment(
+            &mut self,
+            name: &str,
+            _slot: &Cell<Option<Slot>>,
+            _global: &Cell<Option<usize>>,
+            value: &Expression,
+            _span: Span,
+        ) -> String {
+            format!("(= {} {})", name, value.accept(self))
+        }
+
+        fn visit_grouping(&mut self, expression: &Expression) -> String {
+            format!("(group {})", expression.accept(self))
+        }
+
+        fn visit_call(&mut self, callee: &Expression, arguments: &[Expression], _span: Span) -> String {
+            format!("(call {} {})", callee.accept(self), self.all(arguments))
+        }
+
+        fn visit_list(&mut self, elements: &[Expression]) -> String {
+            format!("[{}]", self.all(elements))
+        }
+
+        fn visit_interpolation(&mut self, parts: &[Expression]) -> String {
+            format!("(interpolate {})", self.all(parts))
+        }
+
+        fn visit_map(&mut self, entries: &[(Expression, Expression)], _span: Span) -> String {
+            let entries: Vec<String> = entries
+                .iter()
+                .map(|(key, value)| format!("{}: {}", key.accept(self), value.accept(self)))
+                .collect();
+            format!("{{{}}}", entries.join(" "))
+        }
+
+        fn visit_index(&mut self, target: &Expression, index: &Expression, _span: Span) -> String {
+            format!("(index {} {})", target.accept(self), index.accept(self))
+        }
+
+        fn visit_index_assignment(
+            &mut self,
+            target: &Expression,
+            index: &Expression,
+            value: &Expression,
+            _span: Span,
+        ) -> String {
+            format!(
+                "(= (index {} {}) {})",
+                target.accept(self),
+                index.accept(self),
+                value.accept(self)
+            )
+        }
+    }
+
+    /// Parses `source`, returning the statements and the error messages.
+    fn parse(source: &str) -> (Vec<Statement>, Vec<String>) {
+        let tokens = Scanner::new(source.to_string()).scan().unwrap();
+        let (program, errors) = Parser::new(tokens).parse();
+        let Statement::Program { statements, .. } = program else {
+            unreachable!("the parser always returns a program");
+        };
+
+        (statements, errors.into_iter().map(|error| error.message).collect())
+    }
+
+    /// The shape of a single expression statement.
+    fn shape(expression: &str) -> String {
+        let source = format!("{};", expression);
+        let (statements, errors) = parse(&source);
+        assert!(errors.is_empty(), "{errors:?}");
+
+        match &statements[..] {
+            [Statement::Expression { expression, .. }] => {
+                expression.accept(&mut Shape { source: &source })
+            }
+            other => panic!("expected one expression statement, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn left_associative_operators_group_to_the_left() {
+        assert_eq!(shape("10 - 3 - 2"), "(- (- 10 3) 2)");
+        assert_eq!(shape("8 / 4 / 2"), "(/ (/ 8 4) 2)");
+        assert_eq!(shape("a ~/ b % c * d"), "(* (% (~/ a b) c) d)");
+        assert_eq!(shape("a or b or c"), "(or (or a b) c)");
+        assert_eq!(shape("a and b and c"), "(and (and a b) c)");
+    }
+
+    #[test]
+    fn right_associative_operators_group_to_the_right() {
+        assert_eq!(shape("2 ** 3 ** 2"), "(** 2 (** 3 2))");
+        assert_eq!(shape("a = b = 1"), "(= a (= b 1))");
+        assert_eq!(shape("l[0] = x = 2"), "(= (index l 0) (= x 2))");
+    }
+
+    #[test]
+    fn tighter_operators_bind_first() {
+        assert_eq!(shape("1 + 2 * 3"), "(+ 1 (* 2 3))");
+        assert_eq!(shape("1 * 2 + 3"), "(+ (* 1 2) 3)");
+        assert_eq!(shape("2 * 3 ** 2"), "(* 2 (** 3 2))");
+        assert_eq!(shape("a or b and c"), "(or a (and b c))");
+        assert_eq!(shape("a == 1 and b"), "(and (== a 1) b)");
+        assert_eq!(shape("a + 1 < b * 2"), "(< (+ a 1) (* b 2))");
+        assert_eq!(shape("x = 1 + 2"), "(= x (+ 1 2))");
+    }
+
+    #[test]
+    fn prefix_and_postfix_operators_bind_tightest() {
+        assert_eq!(shape("-2 ** 2"), "(** (- 2) 2)");
+        assert_eq!(shape("!a and b"), "(and (! a) b)");
+        assert_eq!(shape("2 ** 3!"), "(** 2 (3 !))");
+        assert_eq!(shape("-f(1)[0]!"), "(- ((index (call f 1) 0) !))");
+        assert_eq!(shape("(1 + 2) * 3"), "(* (group (+ 1 2)) 3)");
+    }
+
+    #[test]
+    fn comparisons_chain_instead_of_nesting() {
+        assert_eq!(shape("a < b"), "(< a b)");
+        assert_eq!(shape("0 <= x < 10"), "(chain 0 <= x < 10)");
+        assert_eq!(shape("a == b != c < d"), "(chain a == b != c < d)");
+        assert_eq!(shape("(a < b) < c"), "(< (group (< a b)) c)");
+    }
+
+    #[test]
+    fn rejects_code_nested_too_deeply() {
+        fn check() {
+            let nested = |depth| format!("print {}1{};", "(".repeat(depth), ")".repeat(depth));
+
+            // The statement and its expression take a level each.
+            let (_, errors) = parse(&nested(MAX_NESTING - 2));
+            assert!(errors.is_empty(), "{errors:?}");
+            let (_, errors) = parse(&nested(MAX_NESTING - 1));
+            assert_eq!(errors, ["Nested too deeply"]);
+
+            let (_, errors) = parse(&format!("{}{}", "{".repeat(3000), "}".repeat(3000)));
+            assert_eq!(errors, ["Nested too deeply"]);
+
+            let (_, errors) = parse(&format!("if true {{}} {}", "else if true {} ".repeat(3000)));
+            assert_eq!(errors[0], "Nested too deeply");
+
+            for source in [nested(3000), format!("print {}1;", "-".repeat(3000))] {
+                let (statements, errors) = parse(&source);
+                assert!(statements.is_empty());
+                assert_eq!(errors, ["Nested too deeply"]);
+            }
+        }
+
+        // Test threads get a smaller stack than the main thread the limit is
+        // meant for.
+        std::thread::Builder::new()
+            .stack_size(8 * 1024 * 1024)
+            .spawn(check)
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    /// The statements that parsed despite the errors, as the first token of
+    /// each.
+    fn recovered(source: &str) -> (Vec<String>, Vec<String>) {
+        let (statements, errors) = parse(source);
+        let starts = statements
+            .iter()
+            .map(|statement| {
+                let start = statement.span().start;
+                source[start..].split_whitespace().next().unwrap().to_string()
+            })
+            .collect();
+
+        (starts, errors)
+    }
+
+    #[test]
+    fn reports_every_bad_statement_and_keeps_the_rest() {
+        let (statements, errors) = recovered(
+            "print 1 +;
+            print 2;
+            var = 3;
+            print 4 *;
+            print 5;",
+        );
+
+        assert_eq!(statements, ["print", "print"]);
+        assert_eq!(
+            errors,
+            [
+                "Expected expression, found Semicolon",
+                "'Identifier' Expected after 'var'",
+                "Expected expression, found Semicolon",
+            ]
+        );
+    }
+
+    #[test]
+    fn recovers_inside_blocks() {
+        let (statements, errors) = recovered(
+            "{ print 1 +; print 2; }
+            fn f() { var a = ; return 1; }
+            print 3;",
+        );
+
+        assert_eq!(statements, ["{", "fn", "print"]);
+        assert_eq!(
+            errors,
+            [
+                "Expected expression, found Semicolon",
+                "Expected expression, found Semicolon",
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_the_whole_block_of_a_bad_statement() {
+        let (statements, errors) = recovered(
+            "fn (a) { print 1; }
+            print 2;",
+        );
+        assert_eq!(statements, ["print"]);
+        assert_eq!(errors, ["'Identifier' Expected after 'fn'"]);
+
+        let (statements, errors) = recovered(
+            r#"var m = {"a": 1 +};
+            while { if true { print 1; } }
+            print 2;"#,
+        );
+        assert_eq!(statements, ["print"]);
+        assert_eq!(
+            errors,
+            [
+                "Expected expression, found RightBrace",
+                "Expected expression, found If",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_a_stray_closing_brace() {
+        let (statements, errors) = recovered("print 1; } print 2;");
+
+        assert_eq!(statements, ["print", "print"]);
+        assert_eq!(errors, ["Unexpected '}' without a matching '{'"]);
+    }
+}
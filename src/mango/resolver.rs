@@ -0,0 +1,375 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use super::environment::Slot;
+use super::error::ResolveError;
+use super::expression::{self, Expression};
+use super::literal::Literal;
+use super::span::Span;
+use super::statement::{self, Statement};
+use super::token::Token;
+
+/// A variable declared in a local scope. It isn't `defined` until its
+/// initializer has been resolved.
+struct Local {
+    name: String,
+    defined: bool,
+    constant: bool,
+    /// The function it belongs to, counting out from the top level.
+    function: usize,
+    index: usize,
+}
+
+/// Works out, before anything runs, which declaration every variable refers
+/// to. Locals are given the slot they will occupy at runtime; anything not
+/// declared in an enclosing local scope is a global and is looked up by name.
+/// Along the way it rejects redeclarations and assignments to constants.
+///
+/// Scopes mirror the ones the interpreter opens: one per block, per function
+/// call, around a `for` loop and per `for in` iteration. A function's
+/// variables are numbered in one sequence across all of its scopes, the way
+/// they stack up in its environment.
+pub struct Resolver {
+    scopes: Vec<Vec<Local>>,
+    /// The globals declared so far, and whether each is a constant.
+    globals: HashMap<String, bool>,
+    /// How many variables each function being resolved has in scope,
+    /// starting with the top level.
+    functions: Vec<usize>,
+    errors: Vec<ResolveError>,
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Self {
+            scopes: Vec::new(),
+            globals: HashMap::new(),
+            functions: vec![0],
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn resolve(mut self, program: &Statement) -> Result<(), Vec<ResolveError>> {
+        program.accept(&mut self);
+
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(self.errors)
+        }
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(Vec::new());
+    }
+
+    fn end_scope(&mut self) {
+        if let Some(scope) = self.scopes.pop() {
+            *self.functions.last_mut().expect("the top level is never left") -= scope.len();
+        }
+    }
+
+    /// Adds `name` to the innermost scope, not yet defined. Globals only
+    /// need remembering for the checks: they get no slot.
+    fn declare(&mut self, name: &str, constant: bool, span: Span) {
+        let Some(scope) = self.scopes.last_mut() else {
+            if let Some(previous) = self.globals.insert(name.to_string(), constant) {
+                self.errors.push(redeclared(name, previous, span));
+            }
+            return;
+        };
+
+        if let Some(previous) = scope.iter().find(|local| local.name == name) {
+            let error = redeclared(name, previous.constant, span);
+            self.errors.push(error);
+        }
+
+        let function = self.functions.len() - 1;
+        let count = &mut self.functions[function];
+        scope.push(Local {
+            name: name.to_string(),
+            defined: false,
+            constant,
+            function,
+            index: *count,
+        });
+        *count += 1;
+    }
+
+    fn define(&mut self, name: &str) {
+        let local = self
+            .scopes
+            .last_mut()
+            .and_then(|scope| scope.iter_mut().rev().find(|local| local.name == name));
+
+        if let Some(local) = local {
+            local.defined = true;
+        }
+    }
+
+    /// The innermost local called `name`, if it isn't a global.
+    fn lookup(&self, name: &str) -> Option<&Local> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.iter().rev().find(|local| local.name == name))
+    }
+
+    fn resolve_local(&mut self, name: &str, slot: &Cell<Option<Slot>>, span: Span) {
+        let Some(local) = self.lookup(name) else {
+            return;
+        };
+
+        slot.set(Some(Slot {
+            depth: self.functions.len() - 1 - local.function,
+            index: local.index,
+        }));
+
+        if !local.defined {
+            self.errors.push(ResolveError::new(
+                format!("Can't read '{}' in its own initializer", name),
+                span,
+            ));
+        }
+    }
+
+    /// Whether `name` is a constant here. A global declared further down the
+    /// program isn't known yet, so assigning to it is caught when it runs.
+    fn is_constant(&self, name: &str) -> bool {
+        match self.lookup(name) {
+            Some(local) => local.constant,
+            None => self.globals.get(name) == Some(&true),
+        }
+    }
+
+    fn resolve_all(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            statement.accept(self);
+        }
+    }
+}
+
+fn redeclared(name: &str, constant: bool, span: Span) -> ResolveError {
+    let help = if constant {
+        format!("'{name}' is a constant, so pick a different name")
+    } else {
+        format!("to change its value, assign without 'var': {name} = ...")
+    };
+
+    ResolveError::new(format!("'{}' is already declared in this scope", name), span).with_help(help)
+}
+
+impl expression::Visitor<()> for Resolver {
+    fn visit_binary(&mut self, left: &Expression, _operator: &Token, right: &Expression) {
+        left.accept(self);
+        right.accept(self);
+    }
+
+    fn visit_logical(&mut self, left: &Expression, _operator: &Token, right: &Expression) {
+        left.accept(self);
+        right.accept(self);
+    }
+
+    fn visit_comparison(&mut self, operands: &[Expression], _operators: &[Token]) {
+        for operand in operands {
+            operand.accept(self);
+        }
+    }
+
+    fn visit_unary(&mut self, _operator: &Token, right: &Expression, _is_prefix: bool) {
+        right.accept(self);
+    }
+
+    fn visit_literal(&mut self, _literal: &Literal) {}
+
+    fn visit_variable(
+        &mut self,
+        name: &str,
+        slot: &Cell<Option<Slot>>,
+        _global: &Cell<Option<usize>>,
+        span: Span,
+    ) {
+        self.resolve_local(name, slot, span);
+    }
+
+    fn visit_assignment(
+        &mut self,
+        name: &str,
+        slot: &Cell<Option<Slot>>,
+        _global: &Cell<Option<usize>>,
+        value: &Expression,
+        span: Span,
+    ) {
+        value.accept(self);
+        self.resolve_local(name, slot, span);
+
+        if self.is_constant(name) {
+            self.errors.push(
+                ResolveError::new(format!("Can't assign to constant '{}'", name), span)
+                    .with_help(format!("declare '{name}' with 'var' if it needs to change")),
+            );
+        }
+    }
+
+    fn visit_grouping(&mut self, expression: &Expression) {
+        expression.accept(self);
+    }
+
+    fn visit_call(&mut self, callee: &Expression, arguments: &[Expression], _span: Span) {
+        callee.accept(self);
+        for argument in arguments {
+            argument.accept(self);
+        }
+    }
+
+    fn visit_list(&mut self, elements: &[Expression]) {
+        for element in elements {
+            element.accept(self);
+        }
+    }
+
+    fn visit_interpolation(&mut self, parts: &[Expression]) {
+        for part in parts {
+            part.accept(self);
+        }
+    }
+
+    fn visit_map(&mut self, entries: &[(Expression, Expression)], _span: Span) {
+        for (key, value) in entries {
+            key.accept(self);
+            value.accept(self);
+        }
+    }
+
+    fn visit_index(&mut self, target: &Expression, index: &Expression, _span: Span) {
+        target.accept(self);
+        index.accept(self);
+    }
+
+    fn visit_index_assignment(
+        &mut self,
+        target: &Expression,
+        index: &Expression,
+        value: &Expression,
+        _span: Span,
+    ) {
+        target.accept(self);
+        index.accept(self);
+        value.accept(self);
+    }
+}
+
+impl statement::Visitor<()> for Resolver {
+    fn visit_program(&mut self, statements: &[Statement]) {
+        self.resolve_all(statements);
+    }
+
+    fn visit_block(&mut self, statements: &[Statement]) {
+        self.begin_scope();
+        self.resolve_all(statements);
+        self.end_scope();
+    }
+
+    fn visit_expression(&mut self, expression: &Expression) {
+        expression.accept(self);
+    }
+
+    fn visit_variable_declaration(
+        &mut self,
+        name: &str,
+        value: &Expression,
+        constant: bool,
+        span: Span,
+    ) {
+        self.declare(name, constant, span);
+        value.accept(self);
+        self.define(name);
+    }
+
+    fn visit_while(&mut self, condition: &Expression, block: &Statement) {
+        condition.accept(self);
+        block.accept(self);
+    }
+
+    fn visit_for(
+        &mut self,
+        initializer: Option<&Statement>,
+        condition: Option<&Expression>,
+        increment: Option<&Expression>,
+        body: &Statement,
+    ) {
+        self.begin_scope();
+        if let Some(initializer) = initializer {
+            initializer.accept(self);
+        }
+        if let Some(condition) = condition {
+            condition.accept(self);
+        }
+        if let Some(increment) = increment {
+            increment.accept(self);
+        }
+        body.accept(self);
+        self.end_scope();
+    }
+
+    fn visit_for_in(&mut self, name: &str, iterable: &Expression, body: &Statement) {
+        iterable.accept(self);
+
+        self.begin_scope();
+        self.declare(name, false, iterable.span());
+        self.define(name);
+        body.accept(self);
+        self.end_scope();
+    }
+
+    fn visit_break(&mut self) {}
+
+    fn visit_continue(&mut self) {}
+
+    fn visit_if(
+        &mut self,
+        condition: &Expression,
+        then_branch: &Statement,
+        else_branch: Option<&Statement>,
+    ) {
+        condition.accept(self);
+        then_branch.accept(self);
+        if let Some(else_branch) = else_branch {
+            else_branch.accept(self);
+        }
+    }
+
+    fn visit_function(
+        &mut self,
+        name: &str,
+        parameters: &[String],
+        body: &Rc<Vec<Statement>>,
+        span: Span,
+    ) {
+        // Defined straight away, so the body can call itself.
+        self.declare(name, false, span);
+        self.define(name);
+
+        // Parameters share the scope of the body, like they do at runtime.
+        self.functions.push(0);
+        self.begin_scope();
+        for parameter in parameters {
+            self.declare(parameter, false, span);
+            self.define(parameter);
+        }
+        self.resolve_all(body);
+        self.end_scope();
+        self.functions.pop();
+    }
+
+    fn visit_return(&mut self, value: Option<&Expression>, _span: Span) {
+        if let Some(value) = value {
+            value.accept(self);
+        }
+    }
+
+    fn visit_print(&mut self, expression: &Expression) {
+        expression.accept(self);
+    }
+}
@@ -1,192 +1,651 @@
 use super::token::Token;
 use super::token_type::TokenType;
 
+use super::error::LexError;
+use super::integer::Integer;
 use super::literal::Literal;
+use super::span::Span;
 
+/// A cursor over the text being scanned. `offset` is in bytes, so it can be
+/// used to slice the input and for spans, and always sits on a character
+/// boundary; `line` and `column` count characters, for messages.
 pub struct Source {
     input: String,
-    pub position: usize,
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
 }
 
 impl Source {
     pub fn new(input: String) -> Self {
-        Self { input, position: 0 }
+        Self {
+            input,
+            offset: 0,
+            line: 1,
+            column: 1,
+        }
     }
 
     pub fn next(&mut self) -> char {
-        if self.is_at_end() {
+        let Some(char) = self.input[self.offset..].chars().next() else {
             return '\0';
+        };
+        self.offset += char.len_utf8();
+
+        if char == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
         }
 
-        let char = self.input.chars().collect::<Vec<char>>()[self.position];
-        self.position += 1;
-
         char
     }
 
-    pub fn check(&self, c: char) -> bool {
-        return self.peek(0) == c;
+    /// An empty span at the cursor.
+    pub fn location(&self) -> Span {
+        Span::new(self.offset, self.offset, self.line, self.column)
     }
 
-    pub fn peek(&self, offset: usize) -> char {
-        if self.position + offset >= self.input.len() {
-            return '\0';
-        }
+    pub fn check(&self, c: char) -> bool {
+        self.peek(0) == c
+    }
 
-        return self.input.chars().collect::<Vec<char>>()[self.position + offset];
+    /// The character `ahead` characters past the cursor, or '\0' past the
+    /// end. Lookahead is only ever a few characters, so this stays cheap.
+    pub fn peek(&self, ahead: usize) -> char {
+        self.input[self.offset..].chars().nth(ahead).unwrap_or('\0')
     }
 
-    pub fn slice(&mut self, start: usize, end: Option<usize>) -> &str {
-        &self.input[start..end.unwrap_or(self.input.len())]
+    /// The text between two byte offsets, up to the cursor if `end` is
+    /// `None`.
+    pub fn slice(&self, start: usize, end: Option<usize>) -> &str {
+        &self.input[start..end.unwrap_or(self.offset)]
     }
 
     pub fn is_at_end(&self) -> bool {
-        self.position >= self.input.len()
+        self.offset >= self.input.len()
     }
 }
 
 pub struct Scanner {
     source: Source,
-    start: usize,
+    start_span: Span,
+    doc: Option<String>,
+    /// One entry per `${` we are inside of, counting the '{'s opened since.
+    interpolations: Vec<usize>,
 }
 
 impl Scanner {
     pub fn new(input: String) -> Self {
         Self {
-            source: Source::new(input.into()),
-            start: 0,
+            source: Source::new(input),
+            start_span: Span::new(0, 0, 1, 1),
+            doc: None,
+            interpolations: Vec::new(),
+        }
+    }
+
+    /// Scans `input` from byte `offset`, the start of line `line`. The REPL
+    /// scans each entry at the end of everything typed so far, so a span
+    /// from an earlier entry still points at its own text.
+    pub fn resume(input: String, offset: usize, line: usize) -> Self {
+        let mut scanner = Self::new(input);
+        scanner.source.offset = offset;
+        scanner.source.line = line;
+
+        scanner
+    }
+
+    /// The span from the start of the current token up to the cursor.
+    fn span(&self) -> Span {
+        Span {
+            end: self.source.offset,
+            ..self.start_span
         }
     }
 
-    pub fn scan(&mut self) -> Vec<Token> {
+    fn token(&self, kind: TokenType, literal: Option<Literal>) -> Token {
+        Token::new(kind, literal, self.span())
+    }
+
+    pub fn scan(&mut self) -> Result<Vec<Token>, LexError> {
         let mut tokens = Vec::<Token>::new();
 
         while !self.source.is_at_end() {
-            self.start = self.source.position;
+            self.start_span = self.source.location();
 
-            let token = self.scan_token();
-            if let Some(token) = token {
+            let token = self.scan_token()?;
+            if let Some(mut token) = token {
+                token.doc = self.doc.take();
                 tokens.push(token);
             }
         }
-        tokens.push(Token::new(TokenType::End, None));
+        tokens.push(Token::new(TokenType::End, None, self.source.location()));
 
-        return tokens;
+        Ok(tokens)
     }
 
-    pub fn scan_token(&mut self) -> Option<Token> {
+    pub fn scan_token(&mut self) -> Result<Option<Token>, LexError> {
         let char = self.source.next();
 
-        match char {
+        let token = match char {
             ' ' | '\t' | '\r' | '\n' => None, // Skip irrelevant, but valid characters, first.
 
-            ';' => Some(Token::new(TokenType::Semicolon, None)),
+            ';' => Some(self.token(TokenType::Semicolon, None)),
+            ':' => Some(self.token(TokenType::Colon, None)),
+            ',' => Some(self.token(TokenType::Comma, None)),
 
-            '(' => Some(Token::new(TokenType::LeftParen, None)),
-            ')' => Some(Token::new(TokenType::RightParen, None)),
-            '{' => Some(Token::new(TokenType::LeftBrace, None)),
-            '}' => Some(Token::new(TokenType::RightBrace, None)),
-
-            '+' => Some(Token::new(TokenType::Plus, None)),
-            '-' => Some(Token::new(TokenType::Minus, None)),
-            '/' => Some(Token::new(TokenType::Slash, None)),
+            '(' => Some(self.token(TokenType::LeftParen, None)),
+            ')' => Some(self.token(TokenType::RightParen, None)),
+            '{' => {
+                if let Some(depth) = self.interpolations.last_mut() {
+                    *depth += 1;
+                }
+                Some(self.token(TokenType::LeftBrace, None))
+            }
+            '}' => match self.interpolations.last_mut() {
+                // This closes an interpolation, so the string carries on.
+                Some(0) => {
+                    self.interpolations.pop();
+                    Some(self.string()?)
+                }
+                Some(depth) => {
+                    *depth -= 1;
+                    Some(self.token(TokenType::RightBrace, None))
+                }
+                None => Some(self.token(TokenType::RightBrace, None)),
+            },
+            '[' => Some(self.token(TokenType::LeftBracket, None)),
+            ']' => Some(self.token(TokenType::RightBracket, None)),
+
+            '+' => Some(self.token(TokenType::Plus, None)),
+            '-' => Some(self.token(TokenType::Minus, None)),
+            '%' => Some(self.token(TokenType::Percent, None)),
+            // `//` already starts a comment, so integer division is spelled
+            // `~/` instead.
+            '~' if self.source.check('/') => {
+                self.source.next();
+                Some(self.token(TokenType::TildeSlash, None))
+            }
+            '/' => {
+                if self.source.check('/') {
+                    self.line_comment();
+                    None
+                } else if self.source.check('*') {
+                    self.block_comment()?;
+                    None
+                } else {
+                    Some(self.token(TokenType::Slash, None))
+                }
+            }
 
             '=' => {
                 if self.source.check('=') {
                     self.source.next();
-                    Some(Token::new(TokenType::EqualEqual, None))
+                    Some(self.token(TokenType::EqualEqual, None))
                 } else {
-                    Some(Token::new(TokenType::Equal, None))
+                    Some(self.token(TokenType::Equal, None))
                 }
             }
             '!' => {
                 if self.source.check('=') {
                     self.source.next();
-                    Some(Token::new(TokenType::BangEqual, None))
+                    Some(self.token(TokenType::BangEqual, None))
                 } else {
-                    Some(Token::new(TokenType::Bang, None))
+                    Some(self.token(TokenType::Bang, None))
                 }
             }
+            '&' if self.source.check('&') => {
+                self.source.next();
+                Some(self.token(TokenType::And, None))
+            }
+            '|' if self.source.check('|') => {
+                self.source.next();
+                Some(self.token(TokenType::Or, None))
+            }
             '<' => {
                 if self.source.check('=') {
                     self.source.next();
-                    Some(Token::new(TokenType::LessEqual, None))
+                    Some(self.token(TokenType::LessEqual, None))
                 } else {
-                    Some(Token::new(TokenType::Less, None))
+                    Some(self.token(TokenType::Less, None))
                 }
             }
             '>' => {
                 if self.source.check('=') {
                     self.source.next();
-                    Some(Token::new(TokenType::GreaterEqual, None))
+                    Some(self.token(TokenType::GreaterEqual, None))
                 } else {
-                    Some(Token::new(TokenType::Greater, None))
+                    Some(self.token(TokenType::Greater, None))
                 }
             }
             '*' => {
                 if self.source.check('*') {
                     self.source.next();
-                    Some(Token::new(TokenType::StarStar, None))
+                    Some(self.token(TokenType::StarStar, None))
                 } else {
-                    Some(Token::new(TokenType::Star, None))
+                    Some(self.token(TokenType::Star, None))
                 }
             }
 
             '"' => {
-                while !self.source.check('"') && !self.source.is_at_end() {
+                if self.source.check('"') && self.source.peek(1) == '"' {
+                    self.source.next();
+                    self.source.next();
+                    Some(self.raw_string()?)
+                } else {
+                    Some(self.string()?)
+                }
+            }
+            c if c.is_ascii_digit() => Some(self.number(c)?),
+            c if c.is_alphabetic() || c == '_' => {
+                while self.source.peek(0).is_alphanumeric() || self.source.check('_') {
                     self.source.next();
                 }
 
-                if self.source.is_at_end() && self.source.peek(0) != '"' {
-                    panic!("Unterminated string..");
+                let value = self.source.slice(self.start_span.start, None).to_string();
+                let kind = TokenType::from(value.clone());
+
+                Some(self.token(kind, Some(Literal::String(value))))
+            }
+            c => {
+                return Err(LexError::new(
+                    format!("'{c}' Unknown character"),
+                    self.span(),
+                ))
+            }
+        };
+
+        Ok(token)
+    }
+
+    /// Scans the rest of a string, after its opening quote or after the '}'
+    /// closing an interpolation. A string containing `${` is split into a
+    /// `StringPart` for the text before it, the tokens of the embedded
+    /// expression, and further parts up to the closing quote.
+    fn string(&mut self) -> Result<Token, LexError> {
+        let mut value = String::new();
+
+        loop {
+            if self.source.is_at_end() {
+                return Err(LexError::new(
+                    "Unterminated string".to_string(),
+                    self.start_span,
+                ));
+            }
+
+            if self.source.check('\\') {
+                value.push(self.escape()?);
+                continue;
+            }
+
+            match self.source.next() {
+                '"' => break,
+                '$' if self.source.check('{') => {
+                    self.source.next();
+                    self.interpolations.push(0);
+
+                    return Ok(self.token(TokenType::StringPart, Some(Literal::String(value))));
                 }
+                c => value.push(c),
+            }
+        }
+
+        Ok(self.token(TokenType::String, Some(Literal::String(value))))
+    }
 
+    fn escape(&mut self) -> Result<char, LexError> {
+        let start = self.source.location();
+        self.source.next();
+
+        let escaped = match self.source.next() {
+            'n' => Some('\n'),
+            't' => Some('\t'),
+            'r' => Some('\r'),
+            '0' => Some('\0'),
+            '\\' => Some('\\'),
+            '"' => Some('"'),
+            '$' => Some('$'),
+            'u' if self.source.check('{') => {
                 self.source.next();
 
-                let value = self
-                    .source
-                    .slice(self.start + 1, Some(self.source.position - 1))
-                    .to_string();
-                Some(Token::new(TokenType::String, Some(Literal::String(value))))
-            }
-            c if c.is_ascii_digit() => {
-                while self.source.peek(0).is_ascii_digit() && !self.source.is_at_end() {
+                let mut digits = String::new();
+                while self.source.peek(0).is_ascii_hexdigit() {
+                    digits.push(self.source.next());
+                }
+
+                if self.source.check('}') {
                     self.source.next();
+                    u32::from_str_radix(&digits, 16)
+                        .ok()
+                        .and_then(char::from_u32)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        };
+
+        escaped.ok_or_else(|| {
+            LexError::new(
+                "Invalid escape sequence".to_string(),
+                Span {
+                    end: self.source.offset,
+                    ..start
+                },
+            )
+        })
+    }
+
+    /// Scans a `"""` string whose opening quotes were just consumed. These
+    /// can span lines and are taken as written: no escapes, no interpolation.
+    fn raw_string(&mut self) -> Result<Token, LexError> {
+        // Text usually starts on the line after the opening quotes.
+        if self.source.check('\n') {
+            self.source.next();
+        } else if self.source.check('\r') && self.source.peek(1) == '\n' {
+            self.source.next();
+            self.source.next();
+        }
+
+        let mut value = String::new();
+
+        while !(self.source.check('"') && self.source.peek(1) == '"' && self.source.peek(2) == '"') {
+            if self.source.is_at_end() {
+                return Err(LexError::new(
+                    "Unterminated string".to_string(),
+                    Span {
+                        end: self.start_span.start + 3,
+                        ..self.start_span
+                    },
+                ));
+            }
+
+            value.push(self.source.next());
+        }
+
+        self.source.next();
+        self.source.next();
+        self.source.next();
+
+        Ok(self.token(TokenType::String, Some(Literal::String(value))))
+    }
+
+    /// Scans a number literal whose first digit was just consumed. Plain
+    /// digits make an integer, as do `0x`, `0o` and `0b` prefixed ones; a
+    /// fraction or an exponent makes a float. Digits may be grouped with '_'.
+    fn number(&mut self, first: char) -> Result<Token, LexError> {
+        let radix = match self.source.peek(0) {
+            'x' | 'X' if first == '0' => Some(16),
+            'o' | 'O' if first == '0' => Some(8),
+            'b' | 'B' if first == '0' => Some(2),
+            _ => None,
+        };
+
+        let value = match radix {
+            Some(radix) => {
+                self.source.next();
+
+                let digits = self.digits(radix)?;
+                if digits.is_empty() {
+                    return Err(self.number_error("Missing digits after the base prefix"));
                 }
+                self.number_suffix()?;
+
+                Integer::parse(&digits, radix).map(Literal::Integer)
+            }
+            None => {
+                let mut text = first.to_string();
+                text.push_str(&self.digits(10)?);
+                let mut is_float = false;
 
                 if self.source.check('.') && self.source.peek(1).is_ascii_digit() {
                     self.source.next();
+                    text.push('.');
+                    text.push_str(&self.digits(10)?);
+                    is_float = true;
+                }
+
+                if matches!(self.source.peek(0), 'e' | 'E') {
+                    self.source.next();
+                    text.push('e');
+                    if matches!(self.source.peek(0), '+' | '-') {
+                        text.push(self.source.next());
+                    }
 
-                    while self.source.peek(0).is_ascii_digit() {
-                        self.source.next();
+                    let exponent = self.digits(10)?;
+                    if exponent.is_empty() {
+                        return Err(self.number_error("Missing digits in exponent"));
                     }
+                    text.push_str(&exponent);
+                    is_float = true;
                 }
+                self.number_suffix()?;
 
-                let value: f64 = self
-                    .source
-                    .slice(self.start, Some(self.source.position))
-                    .parse()
-                    .unwrap_or_default();
+                if is_float {
+                    text.parse()
+                        .ok()
+                        .filter(|value: &f64| value.is_finite())
+                        .map(Literal::Number)
+                } else {
+                    Integer::parse(&text, 10).map(Literal::Integer)
+                }
+            }
+        };
 
-                Some(Token::new(TokenType::Number, Some(Literal::Number(value))))
+        match value {
+            Some(value) => Ok(self.token(TokenType::Number, Some(value))),
+            None => Err(self.number_error("Number literal out of range")),
+        }
+    }
+
+    /// Consumes a run of digits in `radix` along with '_' separators, which
+    /// are dropped. A separator must sit between two digits.
+    fn digits(&mut self, radix: u32) -> Result<String, LexError> {
+        let mut digits = String::new();
+        let mut after_separator = false;
+
+        loop {
+            let c = self.source.peek(0);
+            if c == '_' {
+                if after_separator {
+                    return Err(self.number_error("Repeated '_' in number literal"));
+                }
+                after_separator = true;
+            } else if c.is_digit(radix) {
+                digits.push(c);
+                after_separator = false;
+            } else {
+                break;
             }
-            c if c.is_alphabetic() => {
-                while self.source.peek(0).is_alphanumeric() {
-                    self.source.next();
+            self.source.next();
+        }
+
+        if after_separator {
+            return Err(self.number_error("Number literal can't end with '_'"));
+        }
+
+        Ok(digits)
+    }
+
+    /// Rejects letters or digits running on from a literal, such as the `2` in
+    /// `0b102` or the `px` in `10px`.
+    fn number_suffix(&mut self) -> Result<(), LexError> {
+        let c = self.source.peek(0);
+        if !c.is_alphanumeric() && c != '_' {
+            return Ok(());
+        }
+
+        while self.source.peek(0).is_alphanumeric() || self.source.check('_') {
+            self.source.next();
+        }
+
+        Err(self.number_error("Invalid number literal"))
+    }
+
+    fn number_error(&self, message: &str) -> LexError {
+        LexError::new(message.to_string(), self.span())
+    }
+
+    /// Skips a `//` comment whose first '/' was just consumed. A `///` doc
+    /// comment is kept, to be attached to the next token.
+    fn line_comment(&mut self) {
+        self.source.next();
+        let is_doc = self.source.check('/') && self.source.peek(1) != '/';
+        if is_doc {
+            self.source.next();
+        }
+
+        let mut text = String::new();
+        while !self.source.check('\n') && !self.source.is_at_end() {
+            text.push(self.source.next());
+        }
+
+        if is_doc {
+            let line = text.strip_prefix(' ').unwrap_or(&text).trim_end();
+            match &mut self.doc {
+                Some(doc) => {
+                    doc.push('\n');
+                    doc.push_str(line);
                 }
+                None => self.doc = Some(line.to_string()),
+            }
+        }
+    }
 
-                let value: String = self
-                    .source
-                    .slice(self.start, Some(self.source.position))
-                    .to_string();
-                let kind = TokenType::from(value.clone());
+    /// Skips a `/* */` comment whose first '/' was just consumed. Block
+    /// comments nest, so commenting out code that has comments just works.
+    fn block_comment(&mut self) -> Result<(), LexError> {
+        self.source.next();
+        let mut depth = 1;
+
+        while depth > 0 {
+            if self.source.is_at_end() {
+                let opener = Span {
+                    end: self.start_span.start + 2,
+                    ..self.start_span
+                };
+
+                return Err(LexError::new(
+                    "Unterminated block comment".to_string(),
+                    opener,
+                ));
+            }
 
-                Some(Token::new(
-                    TokenType::from(kind),
-                    Some(Literal::String(value)),
-                ))
+            match self.source.next() {
+                '/' if self.source.check('*') => {
+                    self.source.next();
+                    depth += 1;
+                }
+                '*' if self.source.check('/') => {
+                    self.source.next();
+                    depth -= 1;
+                }
+                _ => {}
             }
-            c => panic!("'{c}' Unknown character!"),
         }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+
+    /// The message of the error scanning `source`, and the text it points at.
+    fn error(source: &str) -> (String, &str) {
+        let error = Scanner::new(source.to_string()).scan().unwrap_err();
+        (error.message, &source[error.span.start..error.span.end])
+    }
+
+    fn number(source: &str) -> Literal {
+        let tokens = Scanner::new(source.to_string()).scan().unwrap();
+        assert_eq!(tokens.len(), 2, "{source} should be one token");
+        tokens[0].literal.clone()
+    }
+
+    #[test]
+    fn scans_number_literals() {
+        assert_eq!(number("0xFF"), Literal::Integer(Integer::Small(255)));
+        assert_eq!(number("0o17"), Literal::Integer(Integer::Small(15)));
+        assert_eq!(number("0b101"), Literal::Integer(Integer::Small(5)));
+        assert_eq!(number("1_000_000"), Literal::Integer(Integer::Small(1_000_000)));
+        assert_eq!(number("2.5e-3"), Literal::Number(2.5e-3));
+        assert_eq!(number("1E3"), Literal::Number(1000.0));
+    }
+
+    #[test]
+    fn rejects_a_base_prefix_without_digits() {
+        assert_eq!(error("0x;"), ("Missing digits after the base prefix".to_string(), "0x"));
+        assert_eq!(error("0b"), ("Missing digits after the base prefix".to_string(), "0b"));
+    }
+
+    #[test]
+    fn rejects_misplaced_separators() {
+        assert_eq!(error("1__0"), ("Repeated '_' in number literal".to_string(), "1_"));
+        assert_eq!(error("1_ + 2"), ("Number literal can't end with '_'".to_string(), "1_"));
+        assert_eq!(error("0x_"), ("Number literal can't end with '_'".to_string(), "0x_"));
+    }
+
+    #[test]
+    fn rejects_characters_running_on_from_a_number() {
+        assert_eq!(error("0b102"), ("Invalid number literal".to_string(), "0b102"));
+        assert_eq!(error("10px;"), ("Invalid number literal".to_string(), "10px"));
+        assert_eq!(error("1.5f"), ("Invalid number literal".to_string(), "1.5f"));
+    }
+
+    #[test]
+    fn rejects_an_exponent_without_digits() {
+        assert_eq!(error("2e"), ("Missing digits in exponent".to_string(), "2e"));
+        assert_eq!(error("2e+;"), ("Missing digits in exponent".to_string(), "2e+"));
+    }
+
+    #[test]
+    fn rejects_floats_out_of_range() {
+        assert_eq!(error("1e999"), ("Number literal out of range".to_string(), "1e999"));
+    }
+
+    /// Scanning should be linear: the time per byte stays about the same as
+    /// the input doubles. Slow in a debug build, so run it with
+    /// `cargo test --release -- --ignored`.
+    #[test]
+    #[ignore]
+    fn scanning_time_grows_linearly() {
+        // Ordinary code, with some multi-byte text in strings and names.
+        const CHUNK: &str = r#"
+/// Sums the prices, in €.
+fn total(prix) {
+    var somme = 0;
+    for p in prix {
+        somme = somme + p * 1_000 ~/ 3; /* nested /* comment */ */
+    }
+    return "Total: ${somme} €, café ☕ compris";
+}
+print total([1, 2.5, 0xFF, 1e3]);
+"#;
+
+        let per_byte: Vec<f64> = [1, 2, 4, 8]
+            .iter()
+            .map(|megabytes| {
+                let source = CHUNK.repeat(megabytes * 1024 * 1024 / CHUNK.len());
+                let bytes = source.len();
+
+                let start = Instant::now();
+                Scanner::new(source).scan().expect("the chunk scans");
+                start.elapsed().as_nanos() as f64 / bytes as f64
+            })
+            .collect();
+
+        let (first, last) = (per_byte[0], per_byte[per_byte.len() - 1]);
+        assert!(
+            last < first * 2.0,
+            "{:.1} ns/byte at 1 MB but {:.1} ns/byte at 8 MB",
+            first,
+            last
+        );
     }
 }
@@ -0,0 +1,41 @@
+/// A region of source text, as a byte range plus the line and column where it
+/// starts. Lines and columns are 1-based; columns count characters.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize, line: usize, column: usize) -> Self {
+        Self {
+            start,
+            end,
+            line,
+            column,
+        }
+    }
+
+    /// Joins two spans into one covering everything from `self` to `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            start: self.start,
+            end: other.end.max(self.end),
+            line: self.line,
+            column: self.column,
+        }
+    }
+
+    /// An empty span right after the end of this one. The column assumes the
+    /// span sits on a single line of ASCII text.
+    pub fn shrink_to_end(self) -> Span {
+        Span {
+            start: self.end,
+            end: self.end,
+            line: self.line,
+            column: self.column + (self.end - self.start),
+        }
+    }
+}
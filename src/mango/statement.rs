@@ -1,55 +1,190 @@
+use std::rc::Rc;
+
 use super::expression::Expression;
+use super::span::Span;
 
 #[derive(Debug)]
 pub enum Statement {
     Program {
         statements: Vec<Statement>,
+        span: Span,
     },
     Block {
         statements: Vec<Statement>,
+        span: Span,
     },
 
     Expression {
         expression: Expression,
+        span: Span,
     },
     VariableDeclaration {
         name: String,
         value: Expression,
+        constant: bool,
+        doc: Option<String>,
+        span: Span,
     },
     While {
         condition: Expression,
         block: Box<Statement>,
+        span: Span,
+    },
+    For {
+        initializer: Option<Box<Statement>>,
+        condition: Option<Expression>,
+        increment: Option<Expression>,
+        body: Box<Statement>,
+        span: Span,
+    },
+    ForIn {
+        name: String,
+        iterable: Expression,
+        body: Box<Statement>,
+        span: Span,
+    },
+    Break {
+        span: Span,
+    },
+    Continue {
+        span: Span,
+    },
+    If {
+        condition: Expression,
+        then_branch: Box<Statement>,
+        else_branch: Option<Box<Statement>>,
+        span: Span,
+    },
+    Function {
+        name: String,
+        parameters: Vec<String>,
+        body: Rc<Vec<Statement>>,
+        doc: Option<String>,
+        span: Span,
+    },
+    Return {
+        value: Option<Expression>,
+        span: Span,
     },
 
     Print {
         expression: Expression,
+        span: Span,
     },
 }
 
 impl Statement {
     pub fn accept<T, V: Visitor<T>>(&self, visitor: &mut V) -> T {
         match self {
-            Statement::Program { statements } => visitor.visit_program(statements),
-            Statement::Block { statements } => visitor.visit_block(statements),
+            Statement::Program { statements, .. } => visitor.visit_program(statements),
+            Statement::Block { statements, .. } => visitor.visit_block(statements),
 
-            Statement::Expression { expression } => visitor.visit_expression(expression),
-            Statement::VariableDeclaration { name, value } => {
-                visitor.visit_variable_declaration(name, value)
-            }
-            Statement::While { condition, block } => visitor.visit_while(condition, block),
+            Statement::Expression { expression, .. } => visitor.visit_expression(expression),
+            Statement::VariableDeclaration {
+                name,
+                value,
+                constant,
+                span,
+                ..
+            } => visitor.visit_variable_declaration(name, value, *constant, *span),
+            Statement::While {
+                condition, block, ..
+            } => visitor.visit_while(condition, block),
+            Statement::For {
+                initializer,
+                condition,
+                increment,
+                body,
+                ..
+            } => visitor.visit_for(
+                initializer.as_deref(),
+                condition.as_ref(),
+                increment.as_ref(),
+                body,
+            ),
+            Statement::ForIn {
+                name,
+                iterable,
+                body,
+                ..
+            } => visitor.visit_for_in(name, iterable, body),
+            Statement::Break { .. } => visitor.visit_break(),
+            Statement::Continue { .. } => visitor.visit_continue(),
+            Statement::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => visitor.visit_if(condition, then_branch, else_branch.as_deref()),
+            Statement::Function {
+                name,
+                parameters,
+                body,
+                span,
+                ..
+            } => visitor.visit_function(name, parameters, body, *span),
+            Statement::Return { value, span } => visitor.visit_return(value.as_ref(), *span),
 
-            Statement::Print { expression } => visitor.visit_print(expression),
+            Statement::Print { expression, .. } => visitor.visit_print(expression),
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            Statement::Program { span, .. }
+            | Statement::Block { span, .. }
+            | Statement::Expression { span, .. }
+            | Statement::VariableDeclaration { span, .. }
+            | Statement::While { span, .. }
+            | Statement::For { span, .. }
+            | Statement::ForIn { span, .. }
+            | Statement::Break { span }
+            | Statement::Continue { span }
+            | Statement::If { span, .. }
+            | Statement::Function { span, .. }
+            | Statement::Return { span, .. }
+            | Statement::Print { span, .. } => *span,
         }
     }
 }
 
 pub trait Visitor<T> {
-    fn visit_program(&mut self, statements: &Vec<Statement>) -> T;
-    fn visit_block(&mut self, statements: &Vec<Statement>) -> T;
+    fn visit_program(&mut self, statements: &[Statement]) -> T;
+    fn visit_block(&mut self, statements: &[Statement]) -> T;
 
     fn visit_expression(&mut self, expression: &Expression) -> T;
-    fn visit_variable_declaration(&mut self, name: &String, value: &Expression) -> T;
-    fn visit_while(&mut self, condition: &Expression, block: &Box<Statement>) -> T;
+    fn visit_variable_declaration(
+        &mut self,
+        name: &str,
+        value: &Expression,
+        constant: bool,
+        span: Span,
+    ) -> T;
+    fn visit_while(&mut self, condition: &Expression, block: &Statement) -> T;
+    fn visit_for(
+        &mut self,
+        initializer: Option<&Statement>,
+        condition: Option<&Expression>,
+        increment: Option<&Expression>,
+        body: &Statement,
+    ) -> T;
+    fn visit_for_in(&mut self, name: &str, iterable: &Expression, body: &Statement) -> T;
+    fn visit_break(&mut self) -> T;
+    fn visit_continue(&mut self) -> T;
+    fn visit_if(
+        &mut self,
+        condition: &Expression,
+        then_branch: &Statement,
+        else_branch: Option<&Statement>,
+    ) -> T;
+    fn visit_function(
+        &mut self,
+        name: &str,
+        parameters: &[String],
+        body: &Rc<Vec<Statement>>,
+        span: Span,
+    ) -> T;
+    fn visit_return(&mut self, value: Option<&Expression>, span: Span) -> T;
 
     fn visit_print(&mut self, expression: &Expression) -> T;
 }
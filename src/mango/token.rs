@@ -1,17 +1,23 @@
 use super::literal::Literal;
+use super::span::Span;
 use super::token_type::TokenType;
 
 #[derive(Clone, Debug)]
 pub struct Token {
     pub kind: TokenType,
     pub literal: Literal,
+    pub span: Span,
+    /// The `///` doc comment written right before this token, if any.
+    pub doc: Option<String>,
 }
 
 impl Token {
-    pub fn new(kind: TokenType, literal: Option<Literal>) -> Self {
+    pub fn new(kind: TokenType, literal: Option<Literal>, span: Span) -> Self {
         Self {
             kind,
             literal: literal.unwrap_or(Literal::None),
+            span,
+            doc: None,
         }
     }
 }
@@ -1,15 +1,21 @@
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum TokenType {
     Semicolon,
+    Colon,
+    Comma,
 
     LeftParen,
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
 
     Plus,
     Minus,
     Slash,
+    TildeSlash,
+    Percent,
 
     Equal,
     EqualEqual,
@@ -21,17 +27,30 @@ pub enum TokenType {
     GreaterEqual,
     Star,
     StarStar,
+    And,
+    Or,
 
     Var,
+    Const,
     Print,
     While,
+    For,
+    In,
+    Break,
+    Continue,
+    If,
+    Else,
+    Fn,
+    Return,
 
     True,
     False,
+    None,
 
     Number,
     Identifier,
     String,
+    StringPart,
 
     End,
 }
@@ -41,10 +60,23 @@ impl From<String> for TokenType {
         match value.as_str() {
             "true" => TokenType::True,
             "false" => TokenType::False,
+            "none" => TokenType::None,
+
+            "and" => TokenType::And,
+            "or" => TokenType::Or,
 
             "var" => TokenType::Var,
+            "const" => TokenType::Const,
             "print" => TokenType::Print,
             "while" => TokenType::While,
+            "for" => TokenType::For,
+            "in" => TokenType::In,
+            "break" => TokenType::Break,
+            "continue" => TokenType::Continue,
+            "if" => TokenType::If,
+            "else" => TokenType::Else,
+            "fn" => TokenType::Fn,
+            "return" => TokenType::Return,
 
             _ => TokenType::Identifier,
         }
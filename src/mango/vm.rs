@@ -0,0 +1,472 @@
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::fmt::{self, Debug, Formatter};
+use std::rc::Rc;
+
+use super::compiler::{Compiler, Instruction, Prototype};
+use super::environment::Globals;
+use super::error::{OperatorError, RuntimeError};
+use super::integer::Integer;
+use super::interpreter::{get_index, iterate, set_index};
+use super::literal::{Fac, FloorDiv, Literal, Pow};
+use super::map::Map;
+use super::span::Span;
+use super::statement::Statement;
+
+/// A captured variable. It points into the stack while the variable's scope
+/// is running, and holds the value itself once that scope has ended.
+pub enum Upvalue {
+    Open(usize),
+    Closed(Literal),
+}
+
+/// A compiled function together with the variables it captured, created when
+/// its `fn` declaration runs.
+pub struct Closure {
+    pub prototype: Rc<Prototype>,
+    pub upvalues: Vec<Rc<RefCell<Upvalue>>>,
+}
+
+// A closure can capture itself, so printing its upvalues might never
+// terminate.
+impl Debug for Closure {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Closure({})", self.prototype.name)
+    }
+}
+
+/// Frames live on the heap rather than the native stack, so this only has to
+/// stop runaway recursion before it uses up memory.
+const MAX_FRAMES: usize = 64 * 1024;
+
+struct Frame {
+    closure: Rc<Closure>,
+    ip: usize,
+    /// Where the frame's slots start on the stack. Slot 0 is the closure.
+    base: usize,
+}
+
+/// Runs compiled programs. Globals outlive each run, so the REPL keeps its
+/// definitions just like with the tree-walking `Interpreter`.
+pub struct Vm {
+    stack: Vec<Literal>,
+    frames: Vec<Frame>,
+    globals: Globals,
+    open_upvalues: Vec<Rc<RefCell<Upvalue>>>,
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        Self {
+            stack: Vec::new(),
+            frames: Vec::new(),
+            globals: Globals::new(),
+            open_upvalues: Vec::new(),
+        }
+    }
+
+    pub fn interpret(&mut self, program: Statement) -> Result<(), RuntimeError> {
+        let prototype = Compiler::new(&mut self.globals).compile(&program);
+        let closure = Rc::new(Closure {
+            prototype,
+            upvalues: Vec::new(),
+        });
+
+        // A previous run may have stopped on an error part-way through.
+        self.stack.clear();
+        self.frames.clear();
+        self.open_upvalues.clear();
+
+        self.stack.push(Literal::Closure(Rc::clone(&closure)));
+        let result = self.run(Frame {
+            closure,
+            ip: 0,
+            base: 0,
+        });
+
+        // A closure made before the error may have been kept in a global, so
+        // the variables it captured must outlive the stack.
+        if result.is_err() {
+            self.close_upvalues(0);
+        }
+
+        result
+    }
+
+    fn pop(&mut self) -> Literal {
+        self.stack.pop().expect("the compiler keeps the stack balanced")
+    }
+
+    fn peek(&self) -> &Literal {
+        self.stack.last().expect("the compiler keeps the stack balanced")
+    }
+
+    /// The upvalue for stack slot `index`, shared with any closure that has
+    /// already captured it.
+    fn capture(&mut self, index: usize) -> Rc<RefCell<Upvalue>> {
+        let existing = self
+            .open_upvalues
+            .iter()
+            .find(|upvalue| matches!(*upvalue.borrow(), Upvalue::Open(slot) if slot == index));
+
+        if let Some(upvalue) = existing {
+            return Rc::clone(upvalue);
+        }
+
+        let upvalue = Rc::new(RefCell::new(Upvalue::Open(index)));
+        self.open_upvalues.push(Rc::clone(&upvalue));
+        upvalue
+    }
+
+    /// Moves the values of stack slots from `index` up into the upvalues that
+    /// point at them, before those slots are popped.
+    fn close_upvalues(&mut self, index: usize) {
+        let stack = &self.stack;
+        self.open_upvalues.retain(|upvalue| {
+            let slot = match *upvalue.borrow() {
+                Upvalue::Open(slot) if slot >= index => slot,
+                _ => return true,
+            };
+
+            *upvalue.borrow_mut() = Upvalue::Closed(stack[slot].clone());
+            false
+        });
+    }
+
+    fn binary(
+        &mut self,
+        span: Span,
+        operation: impl FnOnce(Literal, Literal) -> Result<Literal, OperatorError>,
+    ) -> Result<(), RuntimeError> {
+        let right = self.pop();
+        let left = self.pop();
+        let value = operation(left, right).map_err(|error| error.at(span))?;
+        self.stack.push(value);
+
+        Ok(())
+    }
+
+    fn unary(
+        &mut self,
+        span: Span,
+        operation: impl FnOnce(Literal) -> Result<Literal, OperatorError>,
+    ) -> Result<(), RuntimeError> {
+        let value = self.pop();
+        let value = operation(value).map_err(|error| error.at(span))?;
+        self.stack.push(value);
+
+        Ok(())
+    }
+
+    fn run(&mut self, mut frame: Frame) -> Result<(), RuntimeError> {
+        loop {
+            let chunk = &frame.closure.prototype.chunk;
+            let instruction = chunk.code[frame.ip];
+            let span = chunk.spans[frame.ip];
+            frame.ip += 1;
+
+            match instruction {
+                Instruction::Constant(index) => {
+                    let value = frame.closure.prototype.chunk.constants[index].clone();
+                    self.stack.push(value);
+                }
+                Instruction::None => self.stack.push(Literal::None),
+                Instruction::Pop => {
+                    self.pop();
+                }
+                Instruction::Tuck => {
+                    let top = self.peek().clone();
+                    self.stack.insert(self.stack.len() - 2, top);
+                }
+
+                Instruction::GetLocal(slot) => {
+                    let value = self.stack[frame.base + slot].clone();
+                    self.stack.push(value);
+                }
+                Instruction::SetLocal(slot) => {
+                    self.stack[frame.base + slot] = self.peek().clone();
+                }
+                Instruction::GetUpvalue(index) => {
+                    let value = match &*frame.closure.upvalues[index].borrow() {
+                        Upvalue::Open(slot) => self.stack[*slot].clone(),
+                        Upvalue::Closed(value) => value.clone(),
+                    };
+                    self.stack.push(value);
+                }
+                Instruction::SetUpvalue(index) => {
+                    let value = self.peek().clone();
+                    match &mut *frame.closure.upvalues[index].borrow_mut() {
+                        Upvalue::Open(slot) => self.stack[*slot] = value,
+                        Upvalue::Closed(closed) => *closed = value,
+                    }
+                }
+                Instruction::GetGlobal(slot) => {
+                    let value = self.globals.get(slot, span)?;
+                    self.stack.push(value);
+                }
+                Instruction::SetGlobal(slot) => {
+                    let value = self.peek().clone();
+                    self.globals.assign(slot, value, span)?;
+                }
+                Instruction::DefineGlobal(slot) => {
+                    let value = self.pop();
+                    self.globals.define(slot, value, false, span)?;
+                }
+                Instruction::DefineConstant(slot) => {
+                    let value = self.pop();
+                    self.globals.define(slot, value, true, span)?;
+                }
+                Instruction::CloseUpvalue => {
+                    self.close_upvalues(self.stack.len() - 1);
+                    self.pop();
+                }
+
+                Instruction::Add => self.binary(span, |a, b| a + b)?,
+                Instruction::Subtract => self.binary(span, |a, b| a - b)?,
+                Instruction::Multiply => self.binary(span, |a, b| a * b)?,
+                Instruction::Divide => self.binary(span, |a, b| a / b)?,
+                Instruction::FloorDivide => self.binary(span, |a, b| a.floor_div(b))?,
+                Instruction::Remainder => self.binary(span, |a, b| a % b)?,
+                Instruction::Power => self.binary(span, |a, b| a.pow(b))?,
+                Instruction::Negate => self.unary(span, |value| -value)?,
+                Instruction::Not => self.unary(span, |value| Ok(!value))?,
+                Instruction::Factorial => self.unary(span, |value| value.fac())?,
+                Instruction::Equal => self.binary(span, |a, b| Ok(Literal::Boolean(a == b)))?,
+                Instruction::NotEqual => self.binary(span, |a, b| Ok(Literal::Boolean(a != b)))?,
+                Instruction::Less => self.binary(span, |a, b| a.order("<", &b, Ordering::is_lt))?,
+                Instruction::LessEqual => {
+                    self.binary(span, |a, b| a.order("<=", &b, Ordering::is_le))?
+                }
+                Instruction::Greater => self.binary(span, |a, b| a.order(">", &b, Ordering::is_gt))?,
+                Instruction::GreaterEqual => {
+                    self.binary(span, |a, b| a.order(">=", &b, Ordering::is_ge))?
+                }
+
+                Instruction::Jump(target) => frame.ip = target,
+                Instruction::JumpIfFalse(target) => {
+                    if !self.peek().is_truthy() {
+                        frame.ip = target;
+                    }
+                }
+                Instruction::JumpIfTrue(target) => {
+                    if self.peek().is_truthy() {
+                        frame.ip = target;
+                    }
+                }
+
+                Instruction::Closure(index) => {
+                    let prototype = Rc::clone(&frame.closure.prototype.chunk.prototypes[index]);
+                    let upvalues = prototype
+                        .upvalues
+                        .iter()
+                        .map(|source| {
+                            if source.is_local {
+                                self.capture(frame.base + source.index)
+                            } else {
+                                Rc::clone(&frame.closure.upvalues[source.index])
+                            }
+                        })
+                        .collect();
+
+                    let closure = Closure {
+                        prototype,
+                        upvalues,
+                    };
+                    self.stack.push(Literal::Closure(Rc::new(closure)));
+                }
+                Instruction::Call(count) => {
+                    let base = self.stack.len() - count - 1;
+
+                    let closure = match &self.stack[base] {
+                        Literal::Closure(closure) => Rc::clone(closure),
+                        other => {
+                            return Err(RuntimeError::new(
+                                format!("'{}' is not callable", other),
+                                span,
+                            ))
+                        }
+                    };
+
+                    let prototype = &closure.prototype;
+                    if count != prototype.arity {
+                        return Err(RuntimeError::new(
+                            format!(
+                                "'{}' expects {} argument(s) but got {}",
+                                prototype.name, prototype.arity, count
+                            ),
+                            span,
+                        ));
+                    }
+
+                    // Every frame but the script's is a call in progress.
+                    if self.frames.len() >= MAX_FRAMES {
+                        return Err(RuntimeError::new(
+                            format!("Stack overflow calling '{}'", prototype.name),
+                            span,
+                        ));
+                    }
+
+                    let caller = std::mem::replace(
+                        &mut frame,
+                        Frame {
+                            closure,
+                            ip: 0,
+                            base,
+                        },
+                    );
+                    self.frames.push(caller);
+                }
+                Instruction::Return => {
+                    let value = self.pop();
+                    self.close_upvalues(frame.base);
+                    self.stack.truncate(frame.base);
+
+                    match self.frames.pop() {
+                        Some(caller) => {
+                            frame = caller;
+                            self.stack.push(value);
+                        }
+                        None => return Ok(()),
+                    }
+                }
+
+                Instruction::List(count) => {
+                    let elements = self.stack.split_off(self.stack.len() - count);
+                    self.stack.push(Literal::List(Rc::new(RefCell::new(elements))));
+                }
+                Instruction::Map => {
+                    self.stack.push(Literal::Map(Rc::new(RefCell::new(Map::new()))));
+                }
+                Instruction::InsertEntry => {
+                    let value = self.pop();
+                    let key = self.pop();
+
+                    let Literal::Map(map) = self.peek() else {
+                        unreachable!("entries are only inserted into map literals");
+                    };
+                    if !map.borrow_mut().insert(key.clone(), value) {
+                        return Err(RuntimeError::new(
+                            format!("A {} can't be used as a map key", key.type_name()),
+                            span,
+                        ));
+                    }
+                }
+                Instruction::Index => {
+                    let index = self.pop();
+                    let target = self.pop();
+                    self.stack.push(get_index(target, &index, span)?);
+                }
+                Instruction::SetIndex => {
+                    let value = self.pop();
+                    let index = self.pop();
+                    let target = self.pop();
+                    self.stack.push(set_index(target, index, value, span)?);
+                }
+                Instruction::Stringify => {
+                    let value = self.pop();
+                    let text = match value {
+                        Literal::String(text) => text,
+                        other => other.to_string(),
+                    };
+                    self.stack.push(Literal::String(text));
+                }
+                Instruction::Concat(count) => {
+                    let mut text = String::new();
+                    for part in self.stack.drain(self.stack.len() - count..) {
+                        if let Literal::String(part) = part {
+                            text.push_str(&part);
+                        }
+                    }
+                    self.stack.push(Literal::String(text));
+                }
+
+                Instruction::Iterate => {
+                    let iterable = self.pop();
+                    let items = iterate(iterable, span)?;
+                    self.stack.push(Literal::List(Rc::new(RefCell::new(items))));
+                }
+                Instruction::Next(slot, exit) => {
+                    let slot = frame.base + slot;
+                    let Literal::Integer(counter) = &self.stack[slot + 1] else {
+                        unreachable!("the counter slot always holds an integer");
+                    };
+                    let position = counter.to_i64().unwrap_or(i64::MAX) as usize;
+
+                    let Literal::List(items) = &self.stack[slot] else {
+                        unreachable!("the items slot always holds a list");
+                    };
+                    let item = items.borrow().get(position).cloned();
+
+                    match item {
+                        Some(item) => {
+                            self.stack[slot + 1] = Literal::Integer(Integer::Small(position as i64 + 1));
+                            self.stack.push(item);
+                        }
+                        None => frame.ip = exit,
+                    }
+                }
+
+                Instruction::Print => {
+                    let value = self.pop();
+                    println!("{}", value);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mango::parser::Parser;
+    use crate::mango::resolver::Resolver;
+    use crate::mango::scanner::Scanner;
+
+    /// Runs `source` on `vm`, as the REPL does with each input.
+    fn run(vm: &mut Vm, source: &str) -> Result<(), RuntimeError> {
+        let tokens = Scanner::new(source.to_string()).scan().unwrap();
+        let (program, errors) = Parser::new(tokens).parse();
+        assert!(errors.is_empty(), "{errors:?}");
+        Resolver::new().resolve(&program).unwrap();
+
+        vm.interpret(program)
+    }
+
+    #[test]
+    fn closures_kept_past_an_error_still_reach_their_variables() {
+        let mut vm = Vm::new();
+        run(&mut vm, "var g;").unwrap();
+        run(
+            &mut vm,
+            "fn make() {
+                var x = 1;
+                var y = 2;
+                fn f() { return x + y; }
+                g = f;
+                return 1 / 0;
+            }",
+        )
+        .unwrap();
+        assert!(run(&mut vm, "make();").is_err());
+
+        run(&mut vm, "var result = g();").unwrap();
+        assert_eq!(vm.globals["result"].to_string(), "3");
+    }
+
+    #[test]
+    fn globals_keep_their_slots_across_inputs() {
+        let mut vm = Vm::new();
+        run(&mut vm, "fn get() { return later; }").unwrap();
+        let error = run(&mut vm, "get();").unwrap_err();
+        assert_eq!(error.message, "Undefined variable 'later'");
+
+        run(&mut vm, "var later = 5; var result = get();").unwrap();
+        assert_eq!(vm.globals["result"].to_string(), "5");
+
+        run(&mut vm, "const fixed = 1;").unwrap();
+        let error = run(&mut vm, "fixed = 2;").unwrap_err();
+        assert_eq!(error.message, "Can't assign to constant 'fixed'");
+        let error = run(&mut vm, "var fixed = 3;").unwrap_err();
+        assert_eq!(error.message, "Can't redeclare constant 'fixed'");
+    }
+}
@@ -0,0 +1,180 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Runs `file`, which must succeed without reporting anything, and returns
+/// what it printed, without the closing timing line.
+fn run(file: &Path, vm: bool) -> String {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_rs-mango"));
+    command.arg(file);
+    if vm {
+        command.arg("--vm");
+    }
+
+    let output = command.output().expect("the interpreter runs");
+    let stdout = String::from_utf8(output.stdout).expect("output is UTF-8");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        output.status.success() && stderr.is_empty(),
+        "{} failed{}:\n{}",
+        file.display(),
+        if vm { " on the VM" } else { "" },
+        stderr
+    );
+
+    stdout
+        .lines()
+        .filter(|line| !line.starts_with("Completed in "))
+        .map(|line| format!("{}\n", line))
+        .collect()
+}
+
+/// Writes `source` to a file of its own, so tests can run in parallel.
+fn program(name: &str, source: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("mango-{}-{}.mg", name, std::process::id()));
+    fs::write(&path, source).expect("the temporary directory is writable");
+
+    path
+}
+
+/// Runs `source` on both backends, checking they print `expected`.
+fn check(name: &str, source: &str, expected: &str) {
+    let path = program(name, source);
+    let (tree, vm) = (run(&path, false), run(&path, true));
+    fs::remove_file(&path).ok();
+
+    assert_eq!(tree, expected, "the tree-walker printed this for {}", name);
+    assert_eq!(vm, expected, "the VM printed this for {}", name);
+}
+
+#[test]
+fn shadowed_variables_in_nested_blocks() {
+    check(
+        "shadowing",
+        "var a = \"global\";
+        {
+            var a = 1;
+            var b = 2;
+            {
+                var a = b + 1;
+                print a;
+                b = 10;
+            }
+            print a;
+            print b;
+        }
+        print a;",
+        "3\n1\n10\nglobal\n",
+    );
+}
+
+#[test]
+fn closures_reach_variables_several_functions_out() {
+    check(
+        "upvalues",
+        "fn outer() {
+            var x = 1;
+            fn middle() {
+                var y = 10;
+                fn inner() {
+                    x = x + 1;
+                    y = y + 1;
+                    return x + y;
+                }
+                return inner;
+            }
+            return middle();
+        }
+        var f = outer();
+        print f();
+        print f();",
+        "13\n15\n",
+    );
+}
+
+#[test]
+fn loop_variables_and_hidden_locals() {
+    check(
+        "loops",
+        "{
+            var before = 100;
+            var fs = [none, none, none];
+            var n = 0;
+            for item in [1, 2, 3] {
+                var doubled = item * 2;
+                fn f() { return before + doubled; }
+                fs[n] = f;
+                n = n + 1;
+            }
+            var total = 0;
+            for f in fs {
+                total = total + f();
+            }
+            print total;
+            for var i = 0; i < 2; i = i + 1 {
+                var j = i;
+                print before + j;
+            }
+        }",
+        "312\n100\n101\n",
+    );
+}
+
+/// Each demo is checked against the `.out` file next to it.
+#[test]
+fn every_demo_prints_its_expected_output_on_both_backends() {
+    let demos = Path::new(env!("CARGO_MANIFEST_DIR")).join("demo");
+    let mut files: Vec<PathBuf> = fs::read_dir(demos)
+        .expect("the demo directory exists")
+        .map(|entry| entry.expect("the demo directory is readable").path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "mg"))
+        .collect();
+    files.sort();
+
+    assert!(!files.is_empty(), "no demos found");
+    for file in files {
+        let expected = fs::read_to_string(file.with_extension("out"))
+            .unwrap_or_else(|_| panic!("{} has no .out file", file.display()));
+
+        assert_eq!(run(&file, false), expected, "the tree-walker printed this for {}", file.display());
+        assert_eq!(run(&file, true), expected, "the VM printed this for {}", file.display());
+    }
+}
+
+#[test]
+fn comparison_chains_evaluate_each_operand_once_and_stop_at_the_first_failure() {
+    check(
+        "chains",
+        r#"fn f(label, value) {
+            print label;
+            return value;
+        }
+        print f("a", 1) < f("b", 2) <= f("c", 2);
+        print f("a", 1) < f("b", 0) < f("c", 3);
+        print f("a", 3) > f("b", 2) == f("c", 2) < f("d", 1) < f("e", 5);
+
+        var count = 0;
+        fn next() {
+            count = count + 1;
+            return count;
+        }
+        print 0 < next() < 2;
+        print count;"#,
+        "a\nb\nc\ntrue\na\nb\nfalse\na\nb\nc\nd\nfalse\ntrue\n1\n",
+    );
+}
+
+#[test]
+fn recursion_a_thousand_calls_deep() {
+    check(
+        "recursion",
+        "fn r(n) {
+            if n == 0 {
+                return 0;
+            }
+            return 1 + r(n - 1);
+        }
+        print r(1000);",
+        "1000\n",
+    );
+}
@@ -0,0 +1,50 @@
+use std::fs;
+use std::process::Command;
+
+/// Runs `source` as a script file and returns the exit status.
+fn status(name: &str, source: &str) -> Option<i32> {
+    let path = std::env::temp_dir().join(format!("mango-cli-{}-{}.mg", name, std::process::id()));
+    fs::write(&path, source).expect("the temporary directory is writable");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rs-mango"))
+        .arg(&path)
+        .output()
+        .expect("the interpreter runs");
+    fs::remove_file(&path).ok();
+
+    output.status.code()
+}
+
+#[test]
+fn exits_zero_when_the_script_succeeds() {
+    assert_eq!(status("ok", "print 1;"), Some(0));
+}
+
+#[test]
+fn exits_65_for_errors_found_before_running() {
+    assert_eq!(status("lex", "print 0x;"), Some(65));
+    assert_eq!(status("parse", "print 1 +;"), Some(65));
+    assert_eq!(status("resolve", "{ var a = 1; var a = 2; }"), Some(65));
+}
+
+#[test]
+fn exits_70_for_runtime_errors() {
+    assert_eq!(status("runtime", "print 1 / 0;"), Some(70));
+}
+
+#[test]
+fn exits_66_for_a_missing_file() {
+    let output = Command::new(env!("CARGO_BIN_EXE_rs-mango"))
+        .arg("no-such-file.mg")
+        .output()
+        .expect("the interpreter runs");
+
+    assert_eq!(output.status.code(), Some(66));
+}
+
+#[test]
+fn exits_70_for_runaway_recursion() {
+    let source = "fn r(n) { return r(n + 1); } r(0);";
+
+    assert_eq!(status("recursion", source), Some(70));
+}